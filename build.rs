@@ -0,0 +1,29 @@
+use std::{process::Command, time::SystemTime};
+
+fn main() {
+    // Builds from a source tarball or inside Docker may have neither git nor the repository, so the
+    // hash can also be passed in explicitly
+    let commit_hash = std::env::var("GIT_COMMIT_HASH").ok().or_else(|| {
+        Command::new("git")
+            .args(["rev-parse", "HEAD"])
+            .output()
+            .ok()
+            .filter(|output| output.status.success())
+            .and_then(|output| String::from_utf8(output.stdout).ok())
+            .map(|hash| hash.trim().to_owned())
+    });
+    println!(
+        "cargo:rustc-env=GIT_COMMIT_HASH={}",
+        commit_hash.unwrap_or_else(|| String::from("unknown"))
+    );
+
+    let build_timestamp = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    println!("cargo:rustc-env=BUILD_TIMESTAMP={build_timestamp}");
+
+    println!("cargo:rerun-if-env-changed=GIT_COMMIT_HASH");
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/refs/heads");
+}
@@ -0,0 +1,35 @@
+use std::path::Path;
+
+use anyhow::Result;
+use ethers::prelude::*;
+use serde::Deserialize;
+
+use crate::custom_serde::i256_dec;
+
+/// A manual correction of one recipient's reward for a period, as decided by governance.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RewardAdjustment {
+    pub period_id: u32,
+    pub recipient: Address,
+    #[serde(with = "i256_dec")]
+    pub staking_reward_delta: I256,
+    #[serde(with = "i256_dec")]
+    pub fee_reward_delta: I256,
+}
+
+pub fn load_adjustments(path: &Path) -> Result<Vec<RewardAdjustment>> {
+    let adjustments: Vec<RewardAdjustment> = serde_json::from_slice(&std::fs::read(path)?)
+        .map_err(|err| anyhow::anyhow!("invalid adjustments file {}: {}", path.display(), err))?;
+
+    if let Some(first) = adjustments.first() {
+        if adjustments
+            .iter()
+            .any(|adjustment| adjustment.period_id != first.period_id)
+        {
+            anyhow::bail!("adjustments file must only contain a single period");
+        }
+    }
+
+    Ok(adjustments)
+}
@@ -0,0 +1,110 @@
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Result;
+use ethers::{
+    prelude::*,
+    utils::{keccak256, to_checksum},
+};
+use log::info;
+use serde::{Deserialize, Serialize};
+
+use crate::{custom_serde::hex_bytes, util::to_canonical_json};
+
+/// Second-operator approval of the computed allocations. Entries are only signed once an
+/// authorized approver has signed the hash of the exact manifest that was written out.
+pub struct ApprovalGate {
+    approvers: HashSet<Address>,
+    manifest_file: PathBuf,
+    approval_file: PathBuf,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ApprovalManifest<'a, T> {
+    #[serde(with = "hex_bytes")]
+    manifest_hash: Vec<u8>,
+    entries: &'a T,
+}
+
+/// EIP-191 signature of an approver over the manifest hash.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Approval {
+    #[serde(with = "hex_bytes")]
+    manifest_hash: Vec<u8>,
+    #[serde(with = "hex_bytes")]
+    signature: Vec<u8>,
+}
+
+impl ApprovalGate {
+    pub fn new(
+        approvers: HashSet<Address>,
+        manifest_file: PathBuf,
+        approval_file: PathBuf,
+    ) -> Self {
+        Self {
+            approvers,
+            manifest_file,
+            approval_file,
+        }
+    }
+
+    /// Writes the manifest for `entries` and checks the approval file against it. Fails until a
+    /// matching approval is in place, so an unapproved period is retried on the next cycle.
+    pub fn ensure_approved<T>(&self, entries: &T) -> Result<()>
+    where
+        T: Serialize,
+    {
+        let manifest_hash = keccak256(to_canonical_json(entries)?);
+        let manifest = ApprovalManifest {
+            manifest_hash: manifest_hash.to_vec(),
+            entries,
+        };
+        std::fs::write(&self.manifest_file, to_canonical_json(&manifest)?)?;
+
+        if !self.approval_file.exists() {
+            anyhow::bail!(
+                "waiting for approval of manifest 0x{} written to {}",
+                hex::encode(manifest_hash),
+                self.manifest_file.display()
+            );
+        }
+
+        let approver = verify_approval(&self.approval_file, &manifest_hash)?;
+        if !self.approvers.contains(&approver) {
+            anyhow::bail!(
+                "approval signed by {} who is not an authorized approver",
+                to_checksum(&approver, None)
+            );
+        }
+
+        info!(
+            "Manifest 0x{} approved by {}",
+            hex::encode(manifest_hash),
+            to_checksum(&approver, None)
+        );
+
+        Ok(())
+    }
+}
+
+/// Returns the address that signed the approval in `path`, provided it's for `manifest_hash`.
+fn verify_approval(path: &Path, manifest_hash: &[u8; 32]) -> Result<Address> {
+    let approval: Approval = serde_json::from_slice(&std::fs::read(path)?)
+        .map_err(|err| anyhow::anyhow!("invalid approval file {}: {}", path.display(), err))?;
+
+    if approval.manifest_hash != manifest_hash {
+        anyhow::bail!(
+            "approval is for manifest 0x{} but computed manifest is 0x{}",
+            hex::encode(&approval.manifest_hash),
+            hex::encode(manifest_hash)
+        );
+    }
+
+    let signature = Signature::try_from(approval.signature.as_slice())
+        .map_err(|err| anyhow::anyhow!("invalid approval signature: {err}"))?;
+    Ok(signature.recover(&manifest_hash[..])?)
+}
@@ -0,0 +1,114 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use anyhow::Result;
+use ethers::{prelude::*, providers::StreamExt};
+use log::{info, warn};
+use reqwest::Url;
+
+use crate::util::full_jitter_backoff;
+
+/// Latest block as seen by the node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChainHead {
+    pub number: u64,
+    pub timestamp: u64,
+}
+
+/// Source of the chain head. With a websocket URL, new heads are pushed by a subscription running
+/// in the background; whenever no subscribed head is available the head is polled over HTTP.
+#[derive(Clone, Default)]
+pub struct ChainHeadSource {
+    latest: Option<Arc<Mutex<Option<ChainHead>>>>,
+}
+
+const WS_RECONNECTS: usize = 5;
+const WS_RESUBSCRIBE_BASE_SECS: u64 = 1;
+const WS_RESUBSCRIBE_MAX_SECS: u64 = 60;
+
+impl ChainHeadSource {
+    /// Polls the head over HTTP on every call.
+    pub fn polling() -> Self {
+        Self { latest: None }
+    }
+
+    /// Subscribes to new heads over `ws_url` in a background task. The subscription is
+    /// re-established with backoff whenever the connection is lost for good.
+    pub fn subscribe(ws_url: Url) -> Self {
+        let latest = Arc::new(Mutex::new(None));
+
+        let task_latest = latest.clone();
+        tokio::spawn(async move {
+            let mut attempt = 0;
+            loop {
+                match follow_heads(&ws_url, &task_latest, &mut attempt).await {
+                    Ok(()) => warn!("Block subscription ended"),
+                    Err(err) => warn!("Block subscription failed: {err}"),
+                }
+                // Don't serve a head that's no longer updated
+                *task_latest.lock().unwrap() = None;
+
+                let delay = full_jitter_backoff(
+                    Duration::from_secs(WS_RESUBSCRIBE_BASE_SECS),
+                    Duration::from_secs(WS_RESUBSCRIBE_MAX_SECS),
+                    attempt,
+                );
+                tokio::time::sleep(delay).await;
+                attempt += 1;
+            }
+        });
+
+        Self {
+            latest: Some(latest),
+        }
+    }
+
+    pub async fn head<M>(&self, provider: &M) -> Result<ChainHead>
+    where
+        M: Middleware,
+        M::Error: 'static,
+    {
+        if let Some(head) = self
+            .latest
+            .as_ref()
+            .and_then(|latest| *latest.lock().unwrap())
+        {
+            return Ok(head);
+        }
+
+        let block = provider
+            .get_block(BlockNumber::Latest)
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("node returned no latest block"))?;
+        chain_head_from_block(&block)
+    }
+}
+
+async fn follow_heads(
+    ws_url: &Url,
+    latest: &Mutex<Option<ChainHead>>,
+    attempt: &mut u32,
+) -> Result<()> {
+    let provider = Provider::<Ws>::connect_with_reconnects(ws_url.as_str(), WS_RECONNECTS).await?;
+    let mut stream = provider.subscribe_blocks().await?;
+    info!("Subscribed to new heads");
+
+    while let Some(block) = stream.next().await {
+        *latest.lock().unwrap() = Some(chain_head_from_block(&block)?);
+        *attempt = 0;
+    }
+
+    Ok(())
+}
+
+fn chain_head_from_block<T>(block: &Block<T>) -> Result<ChainHead> {
+    Ok(ChainHead {
+        number: block
+            .number
+            .ok_or_else(|| anyhow::anyhow!("latest block has no number"))?
+            .as_u64(),
+        timestamp: block.timestamp.as_u64(),
+    })
+}
@@ -0,0 +1,233 @@
+use std::{collections::BTreeMap, path::Path};
+
+use anyhow::Result;
+use ethers::prelude::*;
+use log::info;
+use serde::{Deserialize, Serialize};
+use serde_with::serde_as;
+
+use crate::{
+    custom_serde::{u256_dec, ChecksumedAddress},
+    graphql::{GraphqlClient, RewardClaim},
+};
+
+/// Cumulative amounts claimed per recipient and per period, persisted locally so that only claims
+/// indexed after `last_index` have to be fetched on the next update. Also keeps the totals
+/// distributed for each signed period, which the rollover of the following period is based on.
+#[serde_as]
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ClaimLedger {
+    pub last_index: Option<u64>,
+    #[serde_as(as = "BTreeMap<ChecksumedAddress, _>")]
+    pub claimed: BTreeMap<Address, PoolAmounts>,
+    #[serde(default)]
+    pub claimed_by_period: BTreeMap<u32, PoolAmounts>,
+    #[serde(default)]
+    pub distributed: BTreeMap<u32, PoolAmounts>,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PoolAmounts {
+    #[serde(with = "u256_dec")]
+    pub staking_reward: U256,
+    #[serde(with = "u256_dec")]
+    pub fee_reward: U256,
+}
+
+/// Part of a finished period's rewards nobody claimed, carried over into the next period.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Rollover {
+    pub staking_reward: U256,
+    pub fee_reward: U256,
+}
+
+impl ClaimLedger {
+    pub fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        Ok(serde_json::from_slice(&std::fs::read(path)?)?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        // Write to a temporary file first so that an interrupted run never leaves a truncated ledger
+        let tmp_path = path.with_extension("tmp");
+        std::fs::write(&tmp_path, serde_json::to_vec_pretty(self)?)?;
+        std::fs::rename(&tmp_path, path)?;
+
+        Ok(())
+    }
+
+    pub async fn update(&mut self, graphql_client: &GraphqlClient) -> Result<usize> {
+        let claims = match self.last_index {
+            Some(last_index) => graphql_client.get_reward_claims_after(last_index).await?,
+            None => graphql_client.get_reward_claims().await?,
+        };
+
+        for claim in claims.iter() {
+            self.add_claim(claim);
+        }
+
+        Ok(claims.len())
+    }
+
+    /// Records the totals distributed for `period_id`, replacing any earlier record.
+    pub fn record_distribution(&mut self, period_id: u32, distributed: PoolAmounts) {
+        self.distributed.insert(period_id, distributed);
+    }
+
+    /// Amounts claimed for `period_id` so far.
+    pub fn claimed_for_period(&self, period_id: u32) -> PoolAmounts {
+        self.claimed_by_period
+            .get(&period_id)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    fn add_claim(&mut self, claim: &RewardClaim) {
+        self.claimed
+            .entry(claim.recipient)
+            .or_default()
+            .add(claim.staking_reward, claim.fee_reward);
+        self.claimed_by_period
+            .entry(claim.period_id)
+            .or_default()
+            .add(claim.staking_reward, claim.fee_reward);
+
+        self.last_index = Some(match self.last_index {
+            Some(last_index) => last_index.max(claim.index),
+            None => claim.index,
+        });
+    }
+}
+
+impl PoolAmounts {
+    fn add(&mut self, staking_reward: U256, fee_reward: U256) {
+        self.staking_reward = self
+            .staking_reward
+            .checked_add(staking_reward)
+            .expect("overflow");
+        self.fee_reward = self.fee_reward.checked_add(fee_reward).expect("overflow");
+    }
+}
+
+/// Computes what's left of `period_id` given what was `distributed` for it and what has been
+/// `claimed` of it. Claims exceeding the distributed totals point at a wrong total or a double
+/// claim, so they fail instead of wrapping around.
+pub fn compute_rollover(
+    period_id: u32,
+    distributed: PoolAmounts,
+    claimed: PoolAmounts,
+) -> Result<Rollover> {
+    let unclaimed = |pool: &str, total: U256, claimed: U256| {
+        total.checked_sub(claimed).ok_or_else(|| {
+            anyhow::anyhow!(
+                "{} claimed for period {} exceeds the distributed {}",
+                claimed,
+                period_id,
+                pool,
+            )
+        })
+    };
+
+    Ok(Rollover {
+        staking_reward: unclaimed(
+            "staking reward",
+            distributed.staking_reward,
+            claimed.staking_reward,
+        )?,
+        fee_reward: unclaimed("fees", distributed.fee_reward, claimed.fee_reward)?,
+    })
+}
+
+pub async fn backfill_claims(graphql_client: &GraphqlClient, path: &Path) -> Result<()> {
+    let mut ledger = ClaimLedger::load(path)?;
+
+    let new_claims = ledger.update(graphql_client).await?;
+    ledger.save(path)?;
+
+    info!(
+        "Added {} claims to ledger ({} recipients, last index: {:?})",
+        new_claims,
+        ledger.claimed.len(),
+        ledger.last_index
+    );
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn claim(index: u64, recipient: u64, period_id: u32, staking_reward: u64) -> RewardClaim {
+        RewardClaim {
+            id: format!("claim-{}", index),
+            index,
+            recipient: Address::from_low_u64_be(recipient),
+            period_id,
+            staking_reward: U256::from(staking_reward),
+            fee_reward: U256::from(staking_reward / 10),
+        }
+    }
+
+    fn amounts(staking_reward: u64, fee_reward: u64) -> PoolAmounts {
+        PoolAmounts {
+            staking_reward: U256::from(staking_reward),
+            fee_reward: U256::from(fee_reward),
+        }
+    }
+
+    #[test]
+    fn partial_claims_roll_over_across_two_periods() {
+        let mut ledger = ClaimLedger::default();
+        for claim in [claim(1, 1, 0, 40), claim(2, 2, 0, 20), claim(3, 1, 1, 130)] {
+            ledger.add_claim(&claim);
+        }
+
+        // Period 0 distributed 100 of which 60 were claimed
+        ledger.record_distribution(0, amounts(100, 10));
+        let rollover =
+            compute_rollover(0, ledger.distributed[&0], ledger.claimed_for_period(0)).unwrap();
+        assert_eq!(rollover.staking_reward, U256::from(40));
+        assert_eq!(rollover.fee_reward, U256::from(4));
+
+        // Period 1 distributed its 100 scheduled plus the 40 rolled over, of which 130 were claimed
+        ledger.record_distribution(
+            1,
+            PoolAmounts {
+                staking_reward: U256::from(100) + rollover.staking_reward,
+                fee_reward: U256::from(10) + rollover.fee_reward,
+            },
+        );
+        let rollover =
+            compute_rollover(1, ledger.distributed[&1], ledger.claimed_for_period(1)).unwrap();
+        assert_eq!(rollover.staking_reward, U256::from(10));
+        assert_eq!(rollover.fee_reward, U256::from(1));
+    }
+
+    #[test]
+    fn claims_are_summed_per_recipient_and_period() {
+        let mut ledger = ClaimLedger::default();
+        for claim in [claim(1, 1, 0, 40), claim(2, 2, 0, 20), claim(3, 1, 1, 130)] {
+            ledger.add_claim(&claim);
+        }
+
+        assert_eq!(ledger.claimed_for_period(0), amounts(60, 6));
+        assert_eq!(ledger.claimed_for_period(1), amounts(130, 13));
+        assert_eq!(ledger.claimed_for_period(2), PoolAmounts::default());
+        assert_eq!(
+            ledger.claimed[&Address::from_low_u64_be(1)],
+            amounts(170, 17)
+        );
+        assert_eq!(ledger.last_index, Some(3));
+    }
+
+    #[test]
+    fn claims_exceeding_the_distributed_total_fail() {
+        assert!(compute_rollover(0, amounts(100, 10), amounts(101, 10)).is_err());
+    }
+}
@@ -1,3 +1,6 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
 use ethers::prelude::*;
 use serde::Deserialize;
 
@@ -6,9 +9,15 @@ use crate::custom_serde::u256_dec;
 #[derive(Debug, Clone, Deserialize)]
 #[serde(deny_unknown_fields)]
 pub struct RewardConfig {
+    /// Part of the config schema the worker serves, which is parsed strictly, but not used for
+    /// signing.
+    #[allow(dead_code)]
     pub has_legacy_chain: bool,
     pub exclude_list: Vec<Address>,
     pub staking_reward_schedule: Vec<ScheduledReward>,
+    /// Maps a staker to the address that receives its rewards instead.
+    #[serde(default)]
+    pub delegations: HashMap<Address, Address>,
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -17,4 +26,127 @@ pub struct ScheduledReward {
     pub period_id: u32,
     #[serde(with = "u256_dec")]
     pub reward: U256,
+    #[serde(default)]
+    pub release: ReleaseCurve,
+}
+
+/// How a scheduled reward is spread over periods starting at its `period_id`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase", deny_unknown_fields)]
+pub enum ReleaseCurve {
+    /// The whole amount is released in the scheduled period.
+    #[default]
+    Immediate,
+    /// The amount is released in equal parts over `periods` periods. Rounding remainders are carried
+    /// forward so that the last period releases whatever is left.
+    Linear { periods: u32 },
+}
+
+impl RewardConfig {
+    /// Sum of all scheduled staking rewards released up to and including `period_id`.
+    pub fn scheduled_staking_total(&self, period_id: u32) -> U256 {
+        self.staking_reward_schedule
+            .iter()
+            .fold(U256::zero(), |acc, item| {
+                acc.checked_add(item.released_until(period_id))
+                    .expect("overflow")
+            })
+    }
+
+    /// Scheduled staking rewards released in `period_id` alone.
+    pub fn scheduled_staking_rewards(&self, period_id: u32) -> U256 {
+        self.staking_reward_schedule
+            .iter()
+            .fold(U256::zero(), |acc, item| {
+                let released = match period_id.checked_sub(1) {
+                    Some(previous_period_id) => {
+                        item.released_until(period_id) - item.released_until(previous_period_id)
+                    }
+                    None => item.released_until(period_id),
+                };
+                acc.checked_add(released).expect("overflow")
+            })
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        for window in self.staking_reward_schedule.windows(2) {
+            if window[0].period_id >= window[1].period_id {
+                anyhow::bail!(
+                    "staking reward schedule not in ascending period order at period {}",
+                    window[1].period_id
+                );
+            }
+        }
+        for item in self.staking_reward_schedule.iter() {
+            if item.release == (ReleaseCurve::Linear { periods: 0 }) {
+                anyhow::bail!(
+                    "linear release of period {} must span at least one period",
+                    item.period_id
+                );
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl ScheduledReward {
+    /// Cumulative amount released up to and including `period_id`.
+    fn released_until(&self, period_id: u32) -> U256 {
+        if period_id < self.period_id {
+            return U256::zero();
+        }
+
+        match self.release {
+            ReleaseCurve::Immediate => self.reward,
+            ReleaseCurve::Linear { periods } => {
+                let elapsed = (period_id - self.period_id + 1).min(periods);
+                self.reward.checked_mul(elapsed.into()).expect("overflow") / U256::from(periods)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(release: serde_json::Value) -> RewardConfig {
+        serde_json::from_value(serde_json::json!({
+            "has_legacy_chain": false,
+            "exclude_list": [],
+            "staking_reward_schedule": [
+                { "period_id": 2, "reward": "100" },
+                { "period_id": 3, "reward": "100", "release": release },
+            ],
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn linear_release_spreads_the_reward_and_carries_remainders() {
+        let immediate = config(serde_json::json!({ "type": "immediate" }));
+        let linear = config(serde_json::json!({ "type": "linear", "periods": 3 }));
+
+        let released = |config: &RewardConfig| {
+            (0..8)
+                .map(|period_id| config.scheduled_staking_rewards(period_id).as_u64())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(released(&immediate), vec![0, 0, 100, 100, 0, 0, 0, 0]);
+        assert_eq!(released(&linear), vec![0, 0, 100, 33, 33, 34, 0, 0]);
+
+        for config in [&immediate, &linear] {
+            assert_eq!(config.scheduled_staking_total(7), U256::from(200));
+            config.validate().unwrap();
+        }
+        assert_eq!(linear.scheduled_staking_total(4), U256::from(166));
+    }
+
+    #[test]
+    fn linear_release_over_zero_periods_is_rejected() {
+        let config = config(serde_json::json!({ "type": "linear", "periods": 0 }));
+
+        assert!(config.validate().is_err());
+    }
 }
@@ -1,7 +1,30 @@
-pub use codegen::LnRewardSystem;
+pub use codegen::{
+    ln_reward_system::ClaimRewardForCall, Blocklist, EmissionTracker, Forwarder, LnRewardSystem,
+};
 
 mod codegen {
     use ethers::prelude::*;
 
     abigen!(LnRewardSystem, "./src/contracts/abis/LnRewardSystem.json");
+
+    abigen!(
+        Blocklist,
+        r#"[
+            function isBlocked(address account) external view returns (bool)
+        ]"#
+    );
+
+    abigen!(
+        Forwarder,
+        r#"[
+            function getNonce(address from) external view returns (uint256)
+        ]"#
+    );
+
+    abigen!(
+        EmissionTracker,
+        r#"[
+            function totalDistributed() external view returns (uint256)
+        ]"#
+    );
 }
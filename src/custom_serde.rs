@@ -1,5 +1,6 @@
 use ethers_core::abi::Address;
-use serde_with::SerializeAs;
+use serde::Deserialize;
+use serde_with::{DeserializeAs, SerializeAs};
 
 pub struct ChecksumedAddress;
 
@@ -12,6 +13,15 @@ impl SerializeAs<Address> for ChecksumedAddress {
     }
 }
 
+impl<'de> DeserializeAs<'de, Address> for ChecksumedAddress {
+    fn deserialize_as<D>(deserializer: D) -> Result<Address, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        Address::deserialize(deserializer)
+    }
+}
+
 pub mod u256_dec {
     use ethers::prelude::*;
     use serde::{Deserialize, Deserializer, Serializer};
@@ -28,10 +38,85 @@ pub mod u256_dec {
         D: Deserializer<'de>,
     {
         let value = String::deserialize(deserializer)?;
-        match U256::from_dec_str(&value) {
+        parse::<D::Error>(&value)
+    }
+
+    pub(super) fn parse<E>(value: &str) -> Result<U256, E>
+    where
+        E: serde::de::Error,
+    {
+        U256::from_dec_str(value)
+            .map_err(|err| E::custom(format!("invalid u256 dec string {value}: {err}")))
+    }
+}
+
+pub mod u256_dec_opt {
+    use ethers::prelude::*;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S>(value: &Option<U256>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match value {
+            Some(value) => super::u256_dec::serialize(value, serializer),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Option<U256>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        match Option::<String>::deserialize(deserializer)? {
+            Some(value) => Ok(Some(super::u256_dec::parse::<D::Error>(&value)?)),
+            None => Ok(None),
+        }
+    }
+}
+
+pub mod u256_dec_vec {
+    use ethers::prelude::*;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S>(values: &[U256], serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_seq(values.iter().map(|value| format!("{value}")))
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Vec<U256>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Vec::<String>::deserialize(deserializer)?
+            .iter()
+            .map(|value| super::u256_dec::parse::<D::Error>(value))
+            .collect()
+    }
+}
+
+pub mod i256_dec {
+    use ethers::prelude::*;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S>(value: &I256, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&format!("{value}"))
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<I256, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let value = String::deserialize(deserializer)?;
+        match I256::from_dec_str(&value) {
             Ok(value) => Ok(value),
             Err(err) => Err(serde::de::Error::custom(format!(
-                "invalid u256 dec string: {err}"
+                "invalid i256 dec string: {err}"
             ))),
         }
     }
@@ -39,7 +124,7 @@ pub mod u256_dec {
 
 pub mod checksumed_address {
     use ethers::{prelude::*, utils::to_checksum};
-    use serde::Serializer;
+    use serde::{Deserialize, Deserializer, Serializer};
 
     pub fn serialize<S>(value: &Address, serializer: S) -> Result<S::Ok, S::Error>
     where
@@ -47,6 +132,64 @@ pub mod checksumed_address {
     {
         serializer.serialize_str(&to_checksum(value, None))
     }
+
+    /// Accepts all-lowercase and all-uppercase hex, but a mixed-case address must carry a valid
+    /// checksum, as it likely went through a typo otherwise.
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Address, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let value = String::deserialize(deserializer)?;
+        let address = super::address_de::parse::<D::Error>(&value)?;
+
+        let digits = value.trim_start_matches("0x");
+        let is_mixed_case = digits.chars().any(|c| c.is_ascii_lowercase())
+            && digits.chars().any(|c| c.is_ascii_uppercase());
+        if is_mixed_case && value != to_checksum(&address, None) {
+            return Err(serde::de::Error::custom(format!(
+                "invalid address checksum: {value}"
+            )));
+        }
+
+        Ok(address)
+    }
+}
+
+pub mod address_de {
+    use ethers::prelude::*;
+    use serde::{Deserialize, Deserializer};
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Address, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let value = String::deserialize(deserializer)?;
+        parse::<D::Error>(&value)
+    }
+
+    pub(super) fn parse<E>(value: &str) -> Result<Address, E>
+    where
+        E: serde::de::Error,
+    {
+        value
+            .parse()
+            .map_err(|err| E::custom(format!("invalid address {value}: {err}")))
+    }
+}
+
+/// Unix seconds as a decimal string or an RFC 3339 date, see [crate::util::parse_timestamp].
+pub mod timestamp {
+    use std::time::SystemTime;
+
+    use serde::{Deserialize, Deserializer};
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<SystemTime, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let value = String::deserialize(deserializer)?;
+        crate::util::parse_timestamp(&value).map_err(serde::de::Error::custom)
+    }
 }
 
 pub mod hex_bytes {
@@ -72,3 +215,43 @@ pub mod hex_bytes {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use ethers::prelude::*;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Amounts {
+        #[serde(with = "super::u256_dec_vec")]
+        values: Vec<U256>,
+    }
+
+    fn round_trip(values: Vec<U256>) -> String {
+        let json = serde_json::to_string(&Amounts {
+            values: values.clone(),
+        })
+        .unwrap();
+        let parsed: Amounts = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed.values, values);
+        json
+    }
+
+    #[test]
+    fn u256_dec_vec_round_trips_empty() {
+        assert_eq!(round_trip(vec![]), r#"{"values":[]}"#);
+    }
+
+    #[test]
+    fn u256_dec_vec_round_trips_single() {
+        assert_eq!(round_trip(vec![U256::from(42)]), r#"{"values":["42"]}"#);
+    }
+
+    #[test]
+    fn u256_dec_vec_round_trips_max() {
+        assert_eq!(
+            round_trip(vec![U256::zero(), U256::MAX]),
+            format!(r#"{{"values":["0","{}"]}}"#, U256::MAX)
+        );
+    }
+}
@@ -0,0 +1,212 @@
+use anyhow::Result;
+use ethers::{prelude::*, utils::to_checksum};
+use log::error;
+
+/// Result of splitting a pool among weighted recipients.
+#[derive(Debug, Clone, Default)]
+pub struct Distribution {
+    /// Amount per recipient, in the order of the weights passed in.
+    pub shares: Vec<(Address, U256)>,
+    /// Rounding remainder included in the share of the last recipient.
+    pub dust: U256,
+    /// Part of the pool that couldn't be assigned because no recipient has any weight. This has to
+    /// be rolled over instead of being signed away.
+    pub undistributed: U256,
+}
+
+/// Splits `pool` proportionally to `weights`, assigning rounding dust to the last recipient with
+/// a non-zero weight. `weights` must be in a deterministic order (see
+/// [crate::weights::into_sorted_weights]) for the result to be reproducible.
+///
+/// Periods may have only fees or only staking rewards, so an empty pool yields zero shares without
+/// dividing at all, and a pool without any weight is returned as undistributed.
+pub fn distribute_proportionally(pool: U256, weights: &[(Address, U256)]) -> Distribution {
+    let zero_shares = || {
+        weights
+            .iter()
+            .map(|(address, _)| (*address, U256::zero()))
+            .collect::<Vec<_>>()
+    };
+
+    if pool.is_zero() {
+        return Distribution {
+            shares: zero_shares(),
+            dust: U256::zero(),
+            undistributed: U256::zero(),
+        };
+    }
+
+    let total_weight = weights.iter().fold(U256::zero(), |acc, (_, weight)| {
+        acc.checked_add(*weight).expect("overflow")
+    });
+    if total_weight.is_zero() {
+        return Distribution {
+            shares: zero_shares(),
+            dust: U256::zero(),
+            undistributed: pool,
+        };
+    }
+
+    let mut shares = weights
+        .iter()
+        .map(|(address, weight)| {
+            let share = pool.full_mul(*weight) / total_weight;
+            (*address, U256::try_from(share).expect("overflow"))
+        })
+        .collect::<Vec<_>>();
+
+    let distributed = shares.iter().fold(U256::zero(), |acc, (_, share)| {
+        acc.checked_add(*share).expect("overflow")
+    });
+    let dust = pool - distributed;
+    if let Some(last_index) = weights.iter().rposition(|(_, weight)| !weight.is_zero()) {
+        shares[last_index].1 = shares[last_index].1.checked_add(dust).expect("overflow");
+    }
+
+    Distribution {
+        shares,
+        dust,
+        undistributed: U256::zero(),
+    }
+}
+
+/// Fixed-point precision of the audit path, well above the 18 decimals of the amounts.
+const AUDIT_FRACTION_SCALE: u128 = 1_000_000_000_000_000_000_000_000_000_000_000_000;
+
+/// Splits `pool` like [distribute_proportionally] and, with `audit_math`, recomputes the split
+/// through an independent fixed-point path and fails when the two disagree beyond rounding dust.
+pub fn split_pool(
+    pool: U256,
+    weights: &[(Address, U256)],
+    audit_math: bool,
+) -> Result<Distribution> {
+    let distribution = distribute_proportionally(pool, weights);
+
+    if audit_math {
+        let divergent = audit_distribution(pool, weights, &distribution);
+        if !divergent.is_empty() {
+            for (address, share, expected) in divergent.iter() {
+                error!(
+                    "Math audit: {} gets {} but fixed-point path yields {}",
+                    to_checksum(address, None),
+                    share,
+                    expected
+                );
+            }
+            anyhow::bail!(
+                "math audit failed for {} of {} recipients",
+                divergent.len(),
+                weights.len()
+            );
+        }
+    }
+
+    Ok(distribution)
+}
+
+/// Returns `(recipient, share, fixed-point share)` for every recipient whose share differs from
+/// the fixed-point computation by more than the dust tolerance, which is one wei per recipient since
+/// that's the most the integer path can round away and hand to a single recipient.
+fn audit_distribution(
+    pool: U256,
+    weights: &[(Address, U256)],
+    distribution: &Distribution,
+) -> Vec<(Address, U256, U256)> {
+    let scale = U512::from(AUDIT_FRACTION_SCALE);
+    let total_weight = weights
+        .iter()
+        .fold(U512::zero(), |acc, (_, weight)| acc + U512::from(*weight));
+    let tolerance = U512::from(weights.len());
+
+    let mut divergent = vec![];
+    for ((address, weight), (_, share)) in weights.iter().zip(distribution.shares.iter()) {
+        let expected = if pool.is_zero() || total_weight.is_zero() {
+            U512::zero()
+        } else {
+            let fraction = U512::from(*weight) * scale / total_weight;
+            U512::from(pool) * fraction / scale
+        };
+        let share_wide = U512::from(*share);
+        let difference = if share_wide > expected {
+            share_wide - expected
+        } else {
+            expected - share_wide
+        };
+
+        if difference > tolerance {
+            divergent.push((
+                *address,
+                *share,
+                U256::try_from(expected).unwrap_or(U256::MAX),
+            ));
+        }
+    }
+
+    let distributed = distribution
+        .shares
+        .iter()
+        .fold(U512::from(distribution.undistributed), |acc, (_, share)| {
+            acc + U512::from(*share)
+        });
+    if distribution.shares.len() != weights.len() || distributed != U512::from(pool) {
+        // Pool not conserved; report everything so the operator sees the full split
+        return weights
+            .iter()
+            .zip(distribution.shares.iter())
+            .map(|((address, _), (_, share))| (*address, *share, U256::zero()))
+            .collect();
+    }
+
+    divergent
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn weights() -> Vec<(Address, U256)> {
+        vec![
+            (Address::from_low_u64_be(1), U256::from(1)),
+            (Address::from_low_u64_be(2), U256::from(2)),
+        ]
+    }
+
+    fn amounts(distribution: &Distribution) -> Vec<U256> {
+        distribution
+            .shares
+            .iter()
+            .map(|(_, share)| *share)
+            .collect()
+    }
+
+    #[test]
+    fn fees_only_period() {
+        let staking = split_pool(U256::zero(), &weights(), true).unwrap();
+        let fees = split_pool(U256::from(100), &weights(), true).unwrap();
+
+        assert_eq!(amounts(&staking), vec![U256::zero(), U256::zero()]);
+        assert!(staking.undistributed.is_zero());
+        assert_eq!(amounts(&fees), vec![U256::from(33), U256::from(67)]);
+        assert!(fees.undistributed.is_zero());
+    }
+
+    #[test]
+    fn staking_only_period() {
+        let staking = split_pool(U256::from(300), &weights(), true).unwrap();
+        let fees = split_pool(U256::zero(), &weights(), true).unwrap();
+
+        assert_eq!(amounts(&staking), vec![U256::from(100), U256::from(200)]);
+        assert_eq!(amounts(&fees), vec![U256::zero(), U256::zero()]);
+        assert!(fees.undistributed.is_zero());
+    }
+
+    #[test]
+    fn pool_without_weight_is_undistributed() {
+        let zero_weights = vec![(Address::from_low_u64_be(1), U256::zero())];
+
+        let distribution = split_pool(U256::from(100), &zero_weights, true).unwrap();
+
+        assert_eq!(amounts(&distribution), vec![U256::zero()]);
+        assert_eq!(distribution.undistributed, U256::from(100));
+    }
+}
@@ -0,0 +1,141 @@
+use std::path::Path;
+
+use anyhow::Result;
+use ethers::{prelude::*, types::transaction::eip712::EIP712Domain};
+use serde::Deserialize;
+
+/// EIP-712 domain parameters for an inclusive range of periods, for periods that were signed
+/// against a different contract deployment than the current one.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct DomainOverride {
+    pub from_period_id: u32,
+    pub to_period_id: u32,
+    pub contract_name: String,
+    pub contract_address: Address,
+    pub version: String,
+}
+
+/// Selects the EIP-712 domain to sign a period with.
+#[derive(Debug)]
+pub struct RewardDomains {
+    default: EIP712Domain,
+    overrides: Vec<DomainOverride>,
+}
+
+impl RewardDomains {
+    pub fn new(default: EIP712Domain, mut overrides: Vec<DomainOverride>) -> Result<Self> {
+        overrides.sort_by_key(|item| item.from_period_id);
+
+        for item in overrides.iter() {
+            if item.from_period_id > item.to_period_id {
+                anyhow::bail!(
+                    "domain override range {}-{} is empty",
+                    item.from_period_id,
+                    item.to_period_id
+                );
+            }
+        }
+        for window in overrides.windows(2) {
+            if window[0].to_period_id >= window[1].from_period_id {
+                anyhow::bail!(
+                    "domain overrides overlap at period {}",
+                    window[1].from_period_id
+                );
+            }
+        }
+
+        Ok(Self { default, overrides })
+    }
+
+    /// The domain of the configured contract, used for any period without an override.
+    pub fn default_domain(&self) -> &EIP712Domain {
+        &self.default
+    }
+
+    pub fn for_period(&self, period_id: u32) -> EIP712Domain {
+        match self
+            .overrides
+            .iter()
+            .find(|item| item.from_period_id <= period_id && period_id <= item.to_period_id)
+        {
+            Some(item) => EIP712Domain {
+                name: Some(item.contract_name.clone()),
+                version: Some(item.version.clone()),
+                verifying_contract: Some(item.contract_address),
+                ..self.default.clone()
+            },
+            None => self.default.clone(),
+        }
+    }
+}
+
+pub fn load_domain_overrides(path: &Path) -> Result<Vec<DomainOverride>> {
+    serde_json::from_slice(&std::fs::read(path)?)
+        .map_err(|err| anyhow::anyhow!("invalid domain overrides file {}: {}", path.display(), err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn domain_override(from_period_id: u32, to_period_id: u32, name: &str) -> DomainOverride {
+        DomainOverride {
+            from_period_id,
+            to_period_id,
+            contract_name: name.to_owned(),
+            contract_address: Address::from_low_u64_be(from_period_id.into()),
+            version: String::from("0"),
+        }
+    }
+
+    fn default_domain() -> EIP712Domain {
+        EIP712Domain {
+            name: Some(String::from("RewardSystem")),
+            version: Some(String::from("1")),
+            chain_id: Some(1.into()),
+            verifying_contract: Some(Address::from_low_u64_be(0xbeef)),
+            salt: None,
+        }
+    }
+
+    #[test]
+    fn periods_are_signed_with_the_domain_of_their_range() {
+        let domains = RewardDomains::new(
+            default_domain(),
+            vec![
+                domain_override(10, 19, "RewardSystemV2"),
+                domain_override(0, 9, "RewardSystemV1"),
+            ],
+        )
+        .unwrap();
+
+        for (period_id, name, address) in [
+            (0, "RewardSystemV1", 0),
+            (9, "RewardSystemV1", 0),
+            (10, "RewardSystemV2", 10),
+            (19, "RewardSystemV2", 10),
+        ] {
+            let domain = domains.for_period(period_id);
+            assert_eq!(domain.name.as_deref(), Some(name));
+            assert_eq!(domain.version.as_deref(), Some("0"));
+            assert_eq!(
+                domain.verifying_contract,
+                Some(Address::from_low_u64_be(address))
+            );
+            // The chain id always comes from the connected chain
+            assert_eq!(domain.chain_id, Some(1.into()));
+        }
+        assert_eq!(domains.for_period(20), default_domain());
+    }
+
+    #[test]
+    fn overlapping_or_empty_ranges_are_rejected() {
+        assert!(RewardDomains::new(
+            default_domain(),
+            vec![domain_override(0, 10, "A"), domain_override(10, 19, "B")]
+        )
+        .is_err());
+        assert!(RewardDomains::new(default_domain(), vec![domain_override(5, 4, "A")]).is_err());
+    }
+}
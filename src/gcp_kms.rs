@@ -0,0 +1,257 @@
+use std::time::{Duration, Instant};
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use ethers::{
+    core::k256::{
+        ecdsa::{Signature as KSig, VerifyingKey},
+        elliptic_curve::sec1::ToEncodedPoint,
+    },
+    prelude::*,
+    types::transaction::{eip2718::TypedTransaction, eip712::Eip712},
+    utils::{hash_message, keccak256},
+};
+use reqwest::{Client as HttpClient, ClientBuilder};
+use serde::Deserialize;
+use tokio::sync::Mutex;
+
+const KMS_BASE_URL: &str = "https://cloudkms.googleapis.com/v1";
+const METADATA_TOKEN_URL: &str =
+    "http://metadata.google.internal/computeMetadata/v1/instance/service-account/default/token";
+
+/// Tokens are refreshed this long before they expire so that a request never races the expiry.
+const TOKEN_EXPIRY_MARGIN: Duration = Duration::from_secs(60);
+
+/// Signs with a secp256k1 key version in GCP Cloud KMS, authenticated as the service account of
+/// the instance through the metadata server.
+pub struct GcpSigner {
+    client: HttpClient,
+    key_resource_name: String,
+    address: Address,
+    chain_id: u64,
+    access_token: Mutex<Option<(String, Instant)>>,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum GcpSignerError {
+    #[error(transparent)]
+    Http(#[from] reqwest::Error),
+    #[error("unsuccessful status code from {0}: {1}")]
+    Status(&'static str, reqwest::StatusCode),
+    #[error("invalid public key: {0}")]
+    PublicKey(String),
+    #[error("invalid signature: {0}")]
+    Signature(String),
+    #[error("{0}")]
+    Eip712(String),
+}
+
+#[derive(Deserialize)]
+struct AccessTokenResponse {
+    access_token: String,
+    expires_in: u64,
+}
+
+#[derive(Deserialize)]
+struct PublicKeyResponse {
+    pem: String,
+}
+
+#[derive(Deserialize)]
+struct AsymmetricSignResponse {
+    signature: String,
+}
+
+impl std::fmt::Debug for GcpSigner {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("GcpSigner")
+            .field("key_resource_name", &self.key_resource_name)
+            .field("address", &self.address)
+            .field("chain_id", &self.chain_id)
+            .field("access_token", &"<redacted>")
+            .finish_non_exhaustive()
+    }
+}
+
+impl GcpSigner {
+    /// Fetches the public key of `key_resource_name`, which is the full name of a key version:
+    /// `projects/*/locations/*/keyRings/*/cryptoKeys/*/cryptoKeyVersions/*`.
+    pub async fn new(key_resource_name: &str, chain_id: u64) -> Result<Self, GcpSignerError> {
+        let client = ClientBuilder::new()
+            .timeout(Duration::from_secs(10))
+            .build()
+            .unwrap();
+
+        let access_token = fetch_access_token(&client).await?;
+        let public_key = fetch_public_key(&client, key_resource_name, &access_token.0).await?;
+
+        let public_key = public_key.to_encoded_point(false);
+        let hash = keccak256(&public_key.as_bytes()[1..]);
+
+        Ok(Self {
+            client,
+            key_resource_name: key_resource_name.to_owned(),
+            address: Address::from_slice(&hash[12..]),
+            chain_id,
+            access_token: Mutex::new(Some(access_token)),
+        })
+    }
+
+    async fn access_token(&self) -> Result<String, GcpSignerError> {
+        let mut access_token = self.access_token.lock().await;
+        match access_token.as_ref() {
+            Some((token, expires_at)) if Instant::now() + TOKEN_EXPIRY_MARGIN < *expires_at => {
+                Ok(token.clone())
+            }
+            _ => {
+                let (token, expires_at) = fetch_access_token(&self.client).await?;
+                *access_token = Some((token.clone(), expires_at));
+                Ok(token)
+            }
+        }
+    }
+
+    /// Signs `digest` as is. Cloud KMS returns a DER encoded signature without a recovery id, so `v`
+    /// is set to whichever of 27 and 28 recovers our address.
+    async fn sign_digest(&self, digest: [u8; 32]) -> Result<Signature, GcpSignerError> {
+        let response = self
+            .client
+            .post(format!(
+                "{}/{}:asymmetricSign",
+                KMS_BASE_URL, self.key_resource_name
+            ))
+            .bearer_auth(self.access_token().await?)
+            .json(&serde_json::json!({ "digest": { "sha256": BASE64.encode(digest) } }))
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(GcpSignerError::Status("Cloud KMS", response.status()));
+        }
+        let response: AsymmetricSignResponse = response.json().await?;
+
+        let der = BASE64
+            .decode(response.signature)
+            .map_err(|err| GcpSignerError::Signature(err.to_string()))?;
+        let signature =
+            KSig::from_der(&der).map_err(|err| GcpSignerError::Signature(err.to_string()))?;
+        // Ethereum only accepts the low `s` form
+        let signature = signature.normalize_s().unwrap_or(signature);
+
+        let (r, s) = signature.split_bytes();
+        let signature = Signature {
+            r: U256::from_big_endian(&r),
+            s: U256::from_big_endian(&s),
+            v: 27,
+        };
+        [27u64, 28]
+            .into_iter()
+            .map(|v| Signature { v, ..signature })
+            .find(|signature| {
+                signature
+                    .recover(H256::from(digest))
+                    .map(|recovered| recovered == self.address)
+                    .unwrap_or(false)
+            })
+            .ok_or_else(|| {
+                GcpSignerError::Signature(String::from("no recovery id matches the key"))
+            })
+    }
+}
+
+async fn fetch_access_token(client: &HttpClient) -> Result<(String, Instant), GcpSignerError> {
+    let response = client
+        .get(METADATA_TOKEN_URL)
+        .header("Metadata-Flavor", "Google")
+        .send()
+        .await?;
+    if !response.status().is_success() {
+        return Err(GcpSignerError::Status("metadata server", response.status()));
+    }
+    let response: AccessTokenResponse = response.json().await?;
+
+    Ok((
+        response.access_token,
+        Instant::now() + Duration::from_secs(response.expires_in),
+    ))
+}
+
+async fn fetch_public_key(
+    client: &HttpClient,
+    key_resource_name: &str,
+    access_token: &str,
+) -> Result<VerifyingKey, GcpSignerError> {
+    let response = client
+        .get(format!("{}/{}/publicKey", KMS_BASE_URL, key_resource_name))
+        .bearer_auth(access_token)
+        .send()
+        .await?;
+    if !response.status().is_success() {
+        return Err(GcpSignerError::Status("Cloud KMS", response.status()));
+    }
+    let response: PublicKeyResponse = response.json().await?;
+
+    let der = BASE64
+        .decode(
+            response
+                .pem
+                .lines()
+                .filter(|line| !line.starts_with("-----"))
+                .collect::<String>(),
+        )
+        .map_err(|err| GcpSignerError::PublicKey(err.to_string()))?;
+    let spki = spki::SubjectPublicKeyInfo::try_from(der.as_slice())
+        .map_err(|err| GcpSignerError::PublicKey(err.to_string()))?;
+
+    VerifyingKey::from_sec1_bytes(spki.subject_public_key)
+        .map_err(|err| GcpSignerError::PublicKey(err.to_string()))
+}
+
+#[async_trait::async_trait]
+impl Signer for GcpSigner {
+    type Error = GcpSignerError;
+
+    async fn sign_message<S: Send + Sync + AsRef<[u8]>>(
+        &self,
+        message: S,
+    ) -> Result<Signature, Self::Error> {
+        self.sign_digest(hash_message(message.as_ref()).into())
+            .await
+    }
+
+    async fn sign_transaction(&self, message: &TypedTransaction) -> Result<Signature, Self::Error> {
+        let mut tx_with_chain = message.clone();
+        let chain_id = tx_with_chain
+            .chain_id()
+            .map(|chain_id| chain_id.as_u64())
+            .unwrap_or(self.chain_id);
+        tx_with_chain.set_chain_id(chain_id);
+
+        let mut signature = self.sign_digest(tx_with_chain.sighash().into()).await?;
+        signature.v = to_eip155_v((signature.v - 27) as u8, chain_id);
+
+        Ok(signature)
+    }
+
+    async fn sign_typed_data<T: Eip712 + Send + Sync>(
+        &self,
+        payload: &T,
+    ) -> Result<Signature, Self::Error> {
+        let digest = payload
+            .encode_eip712()
+            .map_err(|err| GcpSignerError::Eip712(err.to_string()))?;
+
+        self.sign_digest(digest).await
+    }
+
+    fn address(&self) -> Address {
+        self.address
+    }
+
+    fn chain_id(&self) -> u64 {
+        self.chain_id
+    }
+
+    fn with_chain_id<T: Into<u64>>(mut self, chain_id: T) -> Self {
+        self.chain_id = chain_id.into();
+        self
+    }
+}
@@ -1,54 +1,152 @@
-use std::time::{Duration, SystemTime};
-
-use anyhow::Result;
+use std::{
+    collections::HashSet,
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant, SystemTime},
+};
+
+use anyhow::{Context, Result};
 use ethers::prelude::*;
-use log::error;
-use reqwest::{Client as HttpClient, Url};
+use futures::{Stream, TryStreamExt};
+use log::{debug, error, info, warn};
+use reqwest::{
+    header::{CONTENT_TYPE, RETRY_AFTER},
+    Client as HttpClient, StatusCode, Url,
+};
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_with::{serde_as, DisplayFromStr};
+use sha2::Digest;
+
+use crate::{
+    custom_serde::{address_de, timestamp, u256_dec},
+    http_log::BodyLogger,
+    rate_limit::RateLimiter,
+    util::{full_jitter_backoff, truncate_for_log},
+};
 
 pub struct GraphqlClient {
     client: HttpClient,
-    query_url: Url,
-    anchor_block: u64,
+    /// Tried in order, so a backup only serves requests while the ones before it are failing.
+    query_urls: Vec<Url>,
+    /// Position in `query_urls` of the endpoint that served the latest response.
+    last_endpoint: AtomicUsize,
+    /// Block every query is anchored to. Moved forward each cycle by [Self::set_anchor_block].
+    anchor_block: AtomicU64,
+    rate_limiter: Option<Arc<RateLimiter>>,
+    body_logger: Option<BodyLogger>,
+    dedup_by_id: bool,
+    batch_size: usize,
+    retry_base_delay: Duration,
+    retry_max_delay: Duration,
+    check_index_gaps: bool,
+}
+
+/// Restricts which entries are fetched. Unset bounds aren't sent, so the subgraph doesn't filter on
+/// them at all.
+#[derive(Debug, Clone, Copy, Default)]
+struct EntryFilter {
+    /// Only entries with a greater index.
+    after_index: Option<u64>,
+    /// Only entries at or after this Unix timestamp.
+    from_timestamp: Option<u64>,
+    /// Only entries before this Unix timestamp.
+    to_timestamp: Option<u64>,
+}
+
+/// Paging state of [GraphqlClient::entries_stream].
+struct BatchCursor {
+    last_index: i64,
+    /// Index the next entry must have when checking for gaps, if known.
+    next_index: Option<u64>,
+    seen_ids: HashSet<String>,
+    duplicate_count: usize,
+    fetched_count: usize,
+    started_at: Instant,
+    done: bool,
 }
 
+#[derive(thiserror::Error, Debug)]
+enum GraphqlError {
+    /// The gateway asked us to slow down, optionally saying for how long via `Retry-After`.
+    #[error("rate limited by the subgraph (retry after: {0:?})")]
+    RateLimited(Option<Duration>),
+}
+
+#[serde_as]
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct DebtEntry {
     pub id: String,
+    #[serde_as(as = "DisplayFromStr")]
     pub index: u64,
+    #[serde(with = "address_de")]
     pub address: Address,
+    #[serde(with = "u256_dec")]
     pub debt_factor: U256,
+    #[serde(with = "u256_dec")]
     pub debt_proportion: U256,
+    #[serde(with = "timestamp")]
     pub timestamp: SystemTime,
 }
 
+#[serde_as]
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[allow(dead_code)]
 pub struct ExchangeEntry {
     pub id: String,
+    #[serde_as(as = "DisplayFromStr")]
     pub index: u64,
+    #[serde(with = "address_de")]
     pub from_addr: Address,
     pub source_key: String,
+    #[serde(with = "u256_dec")]
     pub source_amount: U256,
+    #[serde(with = "address_de")]
     pub dest_addr: Address,
     pub dest_key: String,
+    #[serde(with = "u256_dec")]
     pub dest_recived: U256,
+    #[serde(with = "u256_dec")]
     pub fee_for_pool: U256,
+    #[serde(with = "u256_dec")]
     pub fee_for_foundation: U256,
+    #[serde(with = "timestamp")]
     pub timestamp: SystemTime,
 }
 
+#[serde_as]
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[allow(dead_code)]
 pub struct PerpFeeEntry {
     pub id: String,
+    #[serde_as(as = "DisplayFromStr")]
     pub index: u64,
+    #[serde(with = "u256_dec")]
     pub fee_for_pool: U256,
+    #[serde(with = "u256_dec")]
     pub fee_for_foundation: U256,
+    #[serde(with = "timestamp")]
     pub timestamp: SystemTime,
 }
 
+#[serde_as]
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct RewardClaim {
     pub id: String,
+    #[serde_as(as = "DisplayFromStr")]
     pub index: u64,
+    #[serde(with = "address_de")]
     pub recipient: Address,
+    #[serde_as(as = "DisplayFromStr")]
     pub period_id: u32,
+    #[serde(with = "u256_dec")]
     pub staking_reward: U256,
+    #[serde(with = "u256_dec")]
     pub fee_reward: U256,
 }
 
@@ -58,11 +156,29 @@ struct GraphQueryRequest {
     variables: GraphQueryVariables,
 }
 
+#[serde_as]
 #[derive(Serialize, Deserialize)]
 struct GraphQueryVariables {
     block: u64,
     first: usize,
-    skip: usize,
+    /// Only entries with a greater index are returned. Starts at -1 so that index 0 is included.
+    #[serde_as(as = "DisplayFromStr")]
+    #[serde(rename = "lastIndex")]
+    last_index: i64,
+    /// Unix timestamp of the earliest entry to include. Left out of the request when unset.
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    #[serde(rename = "fromTs", skip_serializing_if = "Option::is_none")]
+    from_timestamp: Option<u64>,
+    /// Unix timestamp from which entries are excluded. Left out of the request when unset.
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    #[serde(rename = "toTs", skip_serializing_if = "Option::is_none")]
+    to_timestamp: Option<u64>,
+}
+
+/// Query without variables.
+#[derive(Serialize)]
+struct StaticQueryRequest {
+    query: &'static str,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -88,52 +204,49 @@ struct RawQueryResponseData<R> {
     entries: Vec<R>,
 }
 
-#[derive(Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
-struct RawDebtEntry {
-    pub id: String,
-    pub index: String,
-    pub address: String,
-    pub debt_factor: String,
-    pub debt_proportion: String,
-    pub timestamp: String,
+/// Entries as returned by the subgraph, which all carry a unique `id` and a monotonic `index`.
+trait SubgraphEntry {
+    fn id(&self) -> &str;
+
+    fn index(&self) -> u64;
 }
 
-#[derive(Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
-struct RawExchangeEntry {
-    pub id: String,
-    pub index: String,
-    pub from_addr: String,
-    pub source_key: String,
-    pub source_amount: String,
-    pub dest_addr: String,
-    pub dest_key: String,
-    pub dest_recived: String,
-    pub fee_for_pool: String,
-    pub fee_for_foundation: String,
-    pub timestamp: String,
+#[derive(Deserialize)]
+struct IntrospectionResponseData {
+    #[serde(rename = "__schema")]
+    schema: IntrospectionSchema,
 }
 
-#[derive(Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
-struct RawPerpFeeEntry {
-    pub id: String,
-    pub index: String,
-    pub fee_for_pool: String,
-    pub fee_for_foundation: String,
-    pub timestamp: String,
+#[derive(Deserialize)]
+struct IntrospectionSchema {
+    types: Vec<IntrospectionType>,
 }
 
-#[derive(Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
-struct RawRewardClaim {
-    pub id: String,
-    pub index: String,
-    pub recipient: String,
-    pub period_id: String,
-    pub staking_reward: String,
-    pub fee_reward: String,
+#[derive(Deserialize)]
+struct IntrospectionType {
+    name: String,
+    fields: Option<Vec<IntrospectionField>>,
+}
+
+#[derive(Deserialize)]
+struct IntrospectionField {
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct MetaResponseData {
+    #[serde(rename = "_meta")]
+    meta: Meta,
+}
+
+#[derive(Deserialize)]
+struct Meta {
+    block: MetaBlock,
+}
+
+#[derive(Deserialize)]
+struct MetaBlock {
+    number: u64,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -143,190 +256,717 @@ struct GraphQueryError {
 }
 
 // Hard-coded params
-const QUERY_ENTRY_COUNT: usize = 1000;
 const GRAPHQL_RETRY_COUNT: u32 = 5;
+const GRAPHQL_RETRY_BASE_DELAY_MILLIS: u64 = 500;
+const GRAPHQL_RETRY_MAX_DELAY_SECS: u64 = 30;
+const RESPONSE_SNIPPET_LENGTH: usize = 200;
 
 impl GraphqlClient {
-    pub fn new(query_url: Url, anchor_block: u64, timeout: Duration) -> Self {
+    #[allow(dead_code)]
+    pub fn new(
+        query_url: Url,
+        anchor_block: u64,
+        timeout: Duration,
+        rate_limiter: Option<Arc<RateLimiter>>,
+        body_logger: Option<BodyLogger>,
+        dedup_by_id: bool,
+        batch_size: usize,
+    ) -> Self {
+        Self::with_endpoints(
+            vec![query_url],
+            anchor_block,
+            timeout,
+            rate_limiter,
+            body_logger,
+            dedup_by_id,
+            batch_size,
+        )
+    }
+
+    /// Fails over to the next endpoint in `query_urls` when one can't be reached or returns a
+    /// server error.
+    pub fn with_endpoints(
+        query_urls: Vec<Url>,
+        anchor_block: u64,
+        timeout: Duration,
+        rate_limiter: Option<Arc<RateLimiter>>,
+        body_logger: Option<BodyLogger>,
+        dedup_by_id: bool,
+        batch_size: usize,
+    ) -> Self {
+        assert!(!query_urls.is_empty(), "no GraphQL endpoint provided");
+
         Self {
             client: reqwest::ClientBuilder::new()
                 .timeout(timeout)
                 .build()
                 .unwrap(),
-            query_url,
-            anchor_block,
+            query_urls,
+            last_endpoint: AtomicUsize::new(0),
+            anchor_block: AtomicU64::new(anchor_block),
+            rate_limiter,
+            body_logger,
+            dedup_by_id,
+            batch_size,
+            retry_base_delay: Duration::from_millis(GRAPHQL_RETRY_BASE_DELAY_MILLIS),
+            retry_max_delay: Duration::from_secs(GRAPHQL_RETRY_MAX_DELAY_SECS),
+            check_index_gaps: false,
         }
     }
 
-    pub async fn get_debt_entries(&self) -> Result<Vec<DebtEntry>> {
-        Self::get_entries_in_batches::<_, RawDebtEntry>(
+    /// Makes every fetch fail unless the fetched indices are contiguous. Only enable this
+    /// for subgraphs that index entries without holes.
+    pub fn with_index_gap_check(mut self, check_index_gaps: bool) -> Self {
+        self.check_index_gaps = check_index_gaps;
+        self
+    }
+
+    /// Delays of the retry backoff: the first retry waits up to `base`, doubling with every attempt
+    /// up to `max`. `max` also caps how long a `Retry-After` is honored.
+    pub fn with_retry_delays(mut self, base: Duration, max: Duration) -> Self {
+        self.retry_base_delay = base;
+        self.retry_max_delay = max;
+        self
+    }
+
+    pub fn anchor_block(&self) -> u64 {
+        self.anchor_block.load(Ordering::Relaxed)
+    }
+
+    /// Moves the block all subsequent `get_*` calls are anchored to, e.g. to a freshly finalized
+    /// block for the next period, without rebuilding the client and its connection pool.
+    pub fn set_anchor_block(&self, block: u64) {
+        self.anchor_block.store(block, Ordering::Relaxed);
+    }
+
+    /// Endpoint that served the latest response, or the primary one if nothing was fetched yet.
+    pub fn last_endpoint(&self) -> &Url {
+        &self.query_urls[self.last_endpoint.load(Ordering::Relaxed)]
+    }
+
+    pub async fn get_debt_entries(
+        &self,
+        from_timestamp: Option<SystemTime>,
+        to_timestamp: Option<SystemTime>,
+    ) -> Result<Vec<DebtEntry>> {
+        Self::get_entries_in_batches::<DebtEntry>(
             self,
+            "debt",
             include_str!("./graphql/debt_entries_query.graphql"),
+            EntryFilter::timestamp_range(from_timestamp, to_timestamp),
         )
         .await
     }
 
-    pub async fn get_exchange_entries(&self) -> Result<Vec<ExchangeEntry>> {
-        Self::get_entries_in_batches::<_, RawExchangeEntry>(
+    pub async fn get_reward_claims(&self) -> Result<Vec<RewardClaim>> {
+        Self::get_entries_in_batches::<RewardClaim>(
             self,
-            include_str!("./graphql/exchange_entries_query.graphql"),
+            "reward claim",
+            include_str!("./graphql/reward_claims_query.graphql"),
+            EntryFilter::default(),
         )
         .await
     }
 
-    pub async fn get_perp_fee_entries(&self) -> Result<Vec<PerpFeeEntry>> {
-        Self::get_entries_in_batches::<_, RawPerpFeeEntry>(
+    /// Fetches only the reward claims with an index greater than `last_index`.
+    pub async fn get_reward_claims_after(&self, last_index: u64) -> Result<Vec<RewardClaim>> {
+        Self::get_entries_in_batches::<RewardClaim>(
             self,
-            include_str!("./graphql/perp_fee_entries_query.graphql"),
+            "reward claim",
+            include_str!("./graphql/reward_claims_query.graphql"),
+            EntryFilter {
+                after_index: Some(last_index),
+                ..Default::default()
+            },
         )
         .await
     }
 
-    pub async fn get_reward_claims(&self) -> Result<Vec<RewardClaim>> {
-        Self::get_entries_in_batches::<_, RawRewardClaim>(
-            self,
-            include_str!("./graphql/reward_claims_query.graphql"),
+    /// Returns the latest block the subgraph has indexed.
+    pub async fn check_synced(&self) -> Result<u64> {
+        let request = StaticQueryRequest {
+            query: include_str!("./graphql/meta_query.graphql"),
+        };
+        let response = self.try_query::<_, MetaResponseData>(&request).await?;
+
+        Ok(response.data.meta.block.number)
+    }
+
+    /// Fails if the subgraph hasn't indexed `anchor_block` yet, since entries from blocks it hasn't
+    /// seen would silently be missing from every fetch.
+    pub async fn ensure_synced(&self) -> Result<()> {
+        let anchor_block = self.anchor_block();
+        let head_block = self.check_synced().await?;
+        if head_block < anchor_block {
+            anyhow::bail!(
+                "subgraph not synced to block {} (indexed head: {})",
+                anchor_block,
+                head_block
+            );
+        }
+
+        debug!(
+            "Subgraph indexed head {} is at or past anchor block {}",
+            head_block, anchor_block
+        );
+        Ok(())
+    }
+
+    /// Hashes the names of all types and their fields so that schema changes which would break
+    /// entry parsing can be detected up front.
+    pub async fn get_schema_hash(&self) -> Result<[u8; 32]> {
+        let request = StaticQueryRequest {
+            query: include_str!("./graphql/schema_introspection_query.graphql"),
+        };
+        let response = self
+            .try_query::<_, IntrospectionResponseData>(&request)
+            .await?;
+
+        let mut field_names = vec![];
+        for schema_type in response.data.schema.types.into_iter() {
+            for field in schema_type.fields.unwrap_or_default().into_iter() {
+                field_names.push(format!("{}.{}", schema_type.name, field.name));
+            }
+        }
+        // Introspection doesn't guarantee any order
+        field_names.sort();
+
+        let mut hasher = sha2::Sha256::default();
+        hasher.update(field_names.join("\n").as_bytes());
+        Ok(hasher.finalize().into())
+    }
+
+    /// Yields the exchange entries batch by batch as they're fetched instead of holding the whole
+    /// history in memory.
+    pub fn get_exchange_entries_stream(
+        &self,
+        from_timestamp: Option<SystemTime>,
+        to_timestamp: Option<SystemTime>,
+    ) -> impl Stream<Item = Result<ExchangeEntry>> + '_ {
+        self.entries_stream::<ExchangeEntry>(
+            "exchange",
+            include_str!("./graphql/exchange_entries_query.graphql"),
+            EntryFilter::timestamp_range(from_timestamp, to_timestamp),
         )
-        .await
     }
 
-    async fn get_entries_in_batches<T, R>(&self, query_str: &str) -> Result<Vec<T>>
+    /// Streaming counterpart of [Self::get_exchange_entries_stream] for perp fees.
+    pub fn get_perp_fee_entries_stream(
+        &self,
+        from_timestamp: Option<SystemTime>,
+        to_timestamp: Option<SystemTime>,
+    ) -> impl Stream<Item = Result<PerpFeeEntry>> + '_ {
+        self.entries_stream::<PerpFeeEntry>(
+            "perp fee",
+            include_str!("./graphql/perp_fee_entries_query.graphql"),
+            EntryFilter::timestamp_range(from_timestamp, to_timestamp),
+        )
+    }
+
+    async fn get_entries_in_batches<T>(
+        &self,
+        entity: &str,
+        query_str: &str,
+        filter: EntryFilter,
+    ) -> Result<Vec<T>>
     where
-        R: TryInto<T> + DeserializeOwned,
+        T: DeserializeOwned + SubgraphEntry,
     {
-        let mut entries = vec![];
-
-        loop {
-            let query = GraphQueryRequest {
-                query: String::from(query_str),
-                variables: GraphQueryVariables {
-                    block: self.anchor_block,
-                    first: QUERY_ENTRY_COUNT,
-                    skip: entries.len(),
-                },
-            };
-
-            let mut ind_retry = 0;
-            let result = loop {
-                match self.try_get_batch::<R>(&query).await {
-                    Ok(value) => break value,
-                    Err(err) => {
-                        error!("GraphQL request attempt {} failed: {}", ind_retry, err);
-                    }
-                }
+        self.entries_stream::<T>(entity, query_str, filter)
+            .try_collect::<Vec<_>>()
+            .await
+    }
 
-                ind_retry += 1;
-                if ind_retry > GRAPHQL_RETRY_COUNT {
-                    anyhow::bail!(
-                        "GraphQL request still failed after {} retries",
-                        GRAPHQL_RETRY_COUNT
+    /// Pages through all entries matching `filter` using the largest index
+    /// seen so far as the cursor. Unlike `skip`, this stays fast for large offsets and entries
+    /// indexed in the meantime can't shift pages.
+    fn entries_stream<'a, T>(
+        &'a self,
+        entity: &'a str,
+        query_str: &'a str,
+        filter: EntryFilter,
+    ) -> impl Stream<Item = Result<T>> + 'a
+    where
+        T: DeserializeOwned + SubgraphEntry + 'a,
+    {
+        let cursor = BatchCursor {
+            last_index: filter.after_index.map_or(-1, |after_index| {
+                i64::try_from(after_index).unwrap_or(i64::MAX)
+            }),
+            // Without a lower bound on the timestamp, the first page must start right after the
+            // cursor or at the very first entry
+            next_index: match (filter.after_index, filter.from_timestamp) {
+                (Some(after_index), _) => Some(after_index + 1),
+                (None, None) => Some(0),
+                (None, Some(_)) => None,
+            },
+            seen_ids: HashSet::new(),
+            duplicate_count: 0,
+            fetched_count: 0,
+            started_at: Instant::now(),
+            done: false,
+        };
+
+        futures::stream::try_unfold(cursor, move |mut cursor| async move {
+            if cursor.done {
+                if cursor.duplicate_count > 0 {
+                    warn!(
+                        "Removed {} duplicate {} entries by id",
+                        cursor.duplicate_count, entity
                     );
                 }
-            };
+                info!(
+                    "Fetched {} {} entries from {} in {} ms",
+                    cursor.fetched_count,
+                    entity,
+                    self.last_endpoint(),
+                    cursor.started_at.elapsed().as_millis()
+                );
+                return Ok::<_, anyhow::Error>(None);
+            }
 
-            let batch_size = result.data.entries.len();
-
-            entries.append(
-                &mut result
-                    .data
-                    .entries
-                    .into_iter()
-                    .map(|item| {
-                        item.try_into()
-                            .map_err(|_| anyhow::anyhow!("error parsing raw result"))
-                    })
-                    .collect::<Result<Vec<_>>>()?,
-            );
+            let batch = self
+                .fetch_batch::<T>(entity, query_str, cursor.last_index, filter)
+                .await?;
+            cursor.done = batch.len() < self.batch_size;
 
-            if batch_size < QUERY_ENTRY_COUNT {
-                break;
+            for item in batch.iter() {
+                let index = i64::try_from(item.index()).map_err(|_| {
+                    anyhow::anyhow!("{} entry index {} out of range", entity, item.index())
+                })?;
+                cursor.last_index = cursor.last_index.max(index);
+            }
+
+            let entries = batch
+                .into_iter()
+                .filter(|item| {
+                    // Re-indexing can shift entries across pages, so the same id may show up
+                    // twice and would be counted twice
+                    if !self.dedup_by_id || cursor.seen_ids.insert(item.id().to_owned()) {
+                        true
+                    } else {
+                        cursor.duplicate_count += 1;
+                        false
+                    }
+                })
+                .collect::<Vec<T>>();
+            cursor.fetched_count += entries.len();
+
+            if self.check_index_gaps {
+                cursor.next_index = validate_contiguous(
+                    entity,
+                    entries.iter().map(|entry| entry.index()),
+                    cursor.next_index,
+                )?;
             }
-        }
 
-        Ok(entries)
+            Ok(Some((
+                futures::stream::iter(entries.into_iter().map(Ok)),
+                cursor,
+            )))
+        })
+        .try_flatten()
     }
 
-    async fn try_get_batch<R>(
+    /// Fetches a single page after `last_index`, retrying with backoff.
+    /// Rows are parsed one by one after the request succeeded, so a malformed row is reported with
+    /// its entry id instead of failing the whole response, and isn't retried.
+    async fn fetch_batch<T>(
         &self,
-        request: &GraphQueryRequest,
-    ) -> Result<GraphQuerySuccessResponse<RawQueryResponseData<R>>>
+        entity: &str,
+        query_str: &str,
+        last_index: i64,
+        filter: EntryFilter,
+    ) -> Result<Vec<T>>
     where
-        R: DeserializeOwned,
+        T: DeserializeOwned,
     {
-        let res = self
-            .client
-            .post(self.query_url.clone())
-            .json(&request)
-            .send()
-            .await?;
+        let query = GraphQueryRequest {
+            query: String::from(query_str),
+            variables: GraphQueryVariables {
+                block: self.anchor_block(),
+                first: self.batch_size,
+                last_index,
+                from_timestamp: filter.from_timestamp,
+                to_timestamp: filter.to_timestamp,
+            },
+        };
+
+        let mut ind_retry = 0;
+        let rows = loop {
+            let mut retry_after = None;
+            match self
+                .try_query::<_, RawQueryResponseData<serde_json::Value>>(&query)
+                .await
+            {
+                Ok(value) => break value.data.entries,
+                Err(err) => {
+                    error!("GraphQL request attempt {} failed: {}", ind_retry, err);
+                    if let Some(GraphqlError::RateLimited(delay)) = err.downcast_ref() {
+                        retry_after = *delay;
+                    }
+                }
+            }
+
+            ind_retry += 1;
+            if ind_retry > GRAPHQL_RETRY_COUNT {
+                anyhow::bail!(
+                    "GraphQL request still failed after {} retries",
+                    GRAPHQL_RETRY_COUNT
+                );
+            }
+
+            // Give an overloaded gateway room to recover instead of retrying right away, but never
+            // wait longer than our own backoff would
+            let delay = match retry_after {
+                Some(retry_after) => retry_after.min(self.retry_max_delay),
+                None => {
+                    full_jitter_backoff(self.retry_base_delay, self.retry_max_delay, ind_retry - 1)
+                }
+            };
+            tokio::time::sleep(delay).await;
+        };
+
+        rows.into_iter()
+            .map(|row| {
+                let id = row
+                    .get("id")
+                    .and_then(|id| id.as_str())
+                    .unwrap_or("<unknown>")
+                    .to_owned();
+                serde_json::from_value(row)
+                    .with_context(|| format!("failed to parse {} entry {}", entity, id))
+            })
+            .collect()
+    }
+
+    async fn try_query<Q, D>(&self, request: &Q) -> Result<GraphQuerySuccessResponse<D>>
+    where
+        Q: Serialize,
+        D: DeserializeOwned,
+    {
+        if let Some(rate_limiter) = &self.rate_limiter {
+            rate_limiter.acquire().await;
+        }
+
+        if let Some(body_logger) = &self.body_logger {
+            body_logger.log_request("GraphQL", &serde_json::to_string(request)?);
+        }
+
+        let res = self.send_with_failover(request).await?;
+
+        let status = res.status();
+        let content_type = res
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or("<none>")
+            .to_owned();
+        let retry_after = res
+            .headers()
+            .get(RETRY_AFTER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| parse_retry_after(value, SystemTime::now()));
+        let body = res.text().await?;
+
+        if let Some(body_logger) = &self.body_logger {
+            body_logger.log_response("GraphQL", &body);
+        }
+
+        if status == StatusCode::TOO_MANY_REQUESTS {
+            return Err(GraphqlError::RateLimited(retry_after).into());
+        }
+        if !status.is_success() {
+            anyhow::bail!(
+                "unexpected status code {}: {}",
+                status,
+                truncate_for_log(&body, RESPONSE_SNIPPET_LENGTH)
+            );
+        }
+
+        let response = serde_json::from_str(&body).map_err(|err| {
+            anyhow::anyhow!(
+                "unable to parse response ({}; content type: {}): {}",
+                err,
+                content_type,
+                truncate_for_log(&body, RESPONSE_SNIPPET_LENGTH)
+            )
+        })?;
 
-        match res.json().await? {
+        match response {
             GraphQueryResponse::Success(result) => Ok(result),
             GraphQueryResponse::Error(err) => Err(anyhow::anyhow!("error: {:?}", err.errors)),
         }
     }
+
+    /// Sends `request` to each endpoint in turn until one is reachable and doesn't respond with a
+    /// server error. The last failure is returned if none does.
+    async fn send_with_failover<Q>(&self, request: &Q) -> Result<reqwest::Response>
+    where
+        Q: Serialize,
+    {
+        let mut last_err = None;
+        for (ind_endpoint, query_url) in self.query_urls.iter().enumerate() {
+            match self
+                .client
+                .post(query_url.clone())
+                .json(request)
+                .send()
+                .await
+            {
+                Ok(res) if res.status().is_server_error() => {
+                    warn!("GraphQL endpoint {} returned {}", query_url, res.status());
+                    last_err = Some(anyhow::anyhow!(
+                        "unexpected status code {} from {}",
+                        res.status(),
+                        query_url
+                    ));
+                }
+                Ok(res) => {
+                    if self.last_endpoint.swap(ind_endpoint, Ordering::Relaxed) != ind_endpoint {
+                        info!("GraphQL requests are now served by {}", query_url);
+                    }
+                    return Ok(res);
+                }
+                Err(err) => {
+                    warn!("GraphQL endpoint {} unreachable: {}", query_url, err);
+                    last_err = Some(err.into());
+                }
+            }
+        }
+
+        Err(last_err.expect("no GraphQL endpoint provided"))
+    }
 }
 
-impl TryFrom<RawDebtEntry> for DebtEntry {
-    type Error = anyhow::Error;
+impl SubgraphEntry for DebtEntry {
+    fn id(&self) -> &str {
+        &self.id
+    }
 
-    fn try_from(value: RawDebtEntry) -> std::result::Result<Self, Self::Error> {
-        Ok(Self {
-            id: value.id,
-            index: value.index.parse()?,
-            address: value.address.parse()?,
-            debt_factor: U256::from_dec_str(&value.debt_factor)?,
-            debt_proportion: U256::from_dec_str(&value.debt_proportion)?,
-            timestamp: SystemTime::UNIX_EPOCH + Duration::from_secs(value.timestamp.parse()?),
-        })
+    fn index(&self) -> u64 {
+        self.index
     }
 }
 
-impl TryFrom<RawExchangeEntry> for ExchangeEntry {
-    type Error = anyhow::Error;
+impl SubgraphEntry for ExchangeEntry {
+    fn id(&self) -> &str {
+        &self.id
+    }
 
-    fn try_from(value: RawExchangeEntry) -> std::result::Result<Self, Self::Error> {
-        Ok(Self {
-            id: value.id,
-            index: value.index.parse()?,
-            from_addr: value.from_addr.parse()?,
-            source_key: value.source_key,
-            source_amount: U256::from_dec_str(&value.source_amount)?,
-            dest_addr: value.dest_addr.parse()?,
-            dest_key: value.dest_key,
-            dest_recived: U256::from_dec_str(&value.dest_recived)?,
-            fee_for_pool: U256::from_dec_str(&value.fee_for_pool)?,
-            fee_for_foundation: U256::from_dec_str(&value.fee_for_foundation)?,
-            timestamp: SystemTime::UNIX_EPOCH + Duration::from_secs(value.timestamp.parse()?),
-        })
+    fn index(&self) -> u64 {
+        self.index
     }
 }
 
-impl TryFrom<RawPerpFeeEntry> for PerpFeeEntry {
-    type Error = anyhow::Error;
+impl SubgraphEntry for PerpFeeEntry {
+    fn id(&self) -> &str {
+        &self.id
+    }
 
-    fn try_from(value: RawPerpFeeEntry) -> std::result::Result<Self, Self::Error> {
-        Ok(Self {
-            id: value.id,
-            index: value.index.parse()?,
-            fee_for_pool: U256::from_dec_str(&value.fee_for_pool)?,
-            fee_for_foundation: U256::from_dec_str(&value.fee_for_foundation)?,
-            timestamp: SystemTime::UNIX_EPOCH + Duration::from_secs(value.timestamp.parse()?),
-        })
+    fn index(&self) -> u64 {
+        self.index
+    }
+}
+
+impl SubgraphEntry for RewardClaim {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn index(&self) -> u64 {
+        self.index
+    }
+}
+
+/// Reads a `Retry-After` value given either as delay seconds or as an HTTP date. A date that has
+/// already passed means retrying right away.
+fn parse_retry_after(value: &str, now: SystemTime) -> Option<Duration> {
+    let value = value.trim();
+    match value.parse::<u64>() {
+        Ok(seconds) => Some(Duration::from_secs(seconds)),
+        Err(_) => httpdate::parse_http_date(value)
+            .ok()
+            .map(|date| date.duration_since(now).unwrap_or_default()),
     }
 }
 
-impl TryFrom<RawRewardClaim> for RewardClaim {
-    type Error = anyhow::Error;
+/// Checks that `indices` form a sequence without holes, starting at `base` if given, and names the
+/// first missing index otherwise. Entries dropped or reordered during pagination show up here.
+/// Returns the index the next page must start at, so that pages can be checked one by one.
+fn validate_contiguous<I>(entity: &str, indices: I, base: Option<u64>) -> Result<Option<u64>>
+where
+    I: Iterator<Item = u64>,
+{
+    let mut indices = indices.collect::<Vec<_>>();
+    indices.sort_unstable();
+
+    let first = match (base, indices.first()) {
+        (Some(base), _) => base,
+        (None, Some(first)) => *first,
+        (None, None) => return Ok(None),
+    };
+    let count = indices.len() as u64;
+    for (offset, index) in indices.into_iter().enumerate() {
+        let expected = first + offset as u64;
+        if index != expected {
+            anyhow::bail!(
+                "{} entries are not contiguous: index {} missing (next fetched index: {})",
+                entity,
+                expected,
+                index
+            );
+        }
+    }
+
+    Ok(Some(first + count))
+}
 
-    fn try_from(value: RawRewardClaim) -> std::result::Result<Self, Self::Error> {
-        Ok(Self {
-            id: value.id,
-            index: value.index.parse()?,
-            recipient: value.recipient.parse()?,
-            period_id: value.period_id.parse()?,
-            staking_reward: U256::from_dec_str(&value.staking_reward)?,
-            fee_reward: U256::from_dec_str(&value.fee_reward)?,
+impl EntryFilter {
+    fn timestamp_range(
+        from_timestamp: Option<SystemTime>,
+        to_timestamp: Option<SystemTime>,
+    ) -> Self {
+        let to_unix_secs = |time: SystemTime| {
+            time.duration_since(SystemTime::UNIX_EPOCH)
+                .map_or(0, |duration| duration.as_secs())
+        };
+
+        Self {
+            after_index: None,
+            from_timestamp: from_timestamp.map(to_unix_secs),
+            to_timestamp: to_timestamp.map(to_unix_secs),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use wiremock::{
+        matchers::{body_partial_json, method},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use super::*;
+
+    fn claim(id: &str, index: u64) -> serde_json::Value {
+        json!({
+            "id": id,
+            "index": index.to_string(),
+            "recipient": format!("{:?}", Address::from_low_u64_be(index + 1)),
+            "periodId": "7",
+            "stakingReward": "100",
+            "feeReward": "10",
         })
     }
+
+    async fn mount_page(server: &MockServer, last_index: i64, entries: Vec<serde_json::Value>) {
+        Mock::given(method("POST"))
+            .and(body_partial_json(
+                json!({ "variables": { "lastIndex": last_index.to_string() } }),
+            ))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(json!({ "data": { "entries": entries } })),
+            )
+            .mount(server)
+            .await;
+    }
+
+    /// Two full pages where re-indexing moved the entry `b` into the second page as well.
+    async fn server_with_duplicate_across_pages() -> MockServer {
+        let server = MockServer::start().await;
+        mount_page(&server, -1, vec![claim("a", 0), claim("b", 1)]).await;
+        mount_page(&server, 1, vec![claim("b", 1), claim("c", 2)]).await;
+        mount_page(&server, 2, vec![]).await;
+        server
+    }
+
+    fn graphql_client(server: &MockServer, dedup_by_id: bool) -> GraphqlClient {
+        GraphqlClient::with_endpoints(
+            vec![server.uri().parse().unwrap()],
+            100,
+            Duration::from_secs(5),
+            None,
+            None,
+            dedup_by_id,
+            2,
+        )
+    }
+
+    #[tokio::test]
+    async fn server_error_is_retried_with_the_configured_delays() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(503))
+            .up_to_n_times(2)
+            .mount(&server)
+            .await;
+        mount_page(&server, -1, vec![claim("a", 0)]).await;
+
+        let started_at = Instant::now();
+        let claims = graphql_client(&server, true)
+            .with_retry_delays(Duration::from_millis(1), Duration::from_millis(2))
+            .get_reward_claims()
+            .await
+            .unwrap();
+
+        assert_eq!(claims.len(), 1);
+        assert!(started_at.elapsed() < Duration::from_millis(500));
+    }
+
+    #[test]
+    fn retry_after_accepts_seconds_and_http_dates() {
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1_445_412_480);
+
+        assert_eq!(
+            parse_retry_after(" 120 ", now),
+            Some(Duration::from_secs(120))
+        );
+        assert_eq!(
+            parse_retry_after("Wed, 21 Oct 2015 07:30:00 GMT", now),
+            Some(Duration::from_secs(120))
+        );
+        assert_eq!(
+            parse_retry_after("Wed, 21 Oct 2015 07:00:00 GMT", now),
+            Some(Duration::ZERO)
+        );
+        assert_eq!(parse_retry_after("soon", now), None);
+    }
+
+    #[tokio::test]
+    async fn duplicate_id_across_pages_is_counted_once() {
+        let server = server_with_duplicate_across_pages().await;
+
+        let claims = graphql_client(&server, true)
+            .get_reward_claims()
+            .await
+            .unwrap();
+
+        assert_eq!(
+            claims
+                .iter()
+                .map(|claim| claim.id.as_str())
+                .collect::<Vec<_>>(),
+            vec!["a", "b", "c"]
+        );
+    }
+
+    #[tokio::test]
+    async fn duplicates_are_kept_without_dedup_by_id() {
+        let server = server_with_duplicate_across_pages().await;
+
+        let claims = graphql_client(&server, false)
+            .get_reward_claims()
+            .await
+            .unwrap();
+
+        assert_eq!(
+            claims
+                .iter()
+                .map(|claim| claim.id.as_str())
+                .collect::<Vec<_>>(),
+            vec!["a", "b", "b", "c"]
+        );
+    }
 }
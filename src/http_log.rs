@@ -0,0 +1,92 @@
+use std::fmt::Debug;
+
+use async_trait::async_trait;
+use ethers::providers::{Http, HttpClientError, JsonRpcClient};
+use log::trace;
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::util::truncate_for_log;
+
+/// Logs HTTP bodies at trace level for debugging. Configured secrets are replaced before anything
+/// is logged, and headers (including `Authorization`) are never logged.
+#[derive(Debug, Clone)]
+pub struct BodyLogger {
+    max_chars: usize,
+    secrets: Vec<String>,
+}
+
+/// JSON-RPC transport that logs request params and responses through an optional [BodyLogger].
+#[derive(Debug)]
+pub struct LoggedHttp {
+    inner: Http,
+    body_logger: Option<BodyLogger>,
+}
+
+impl BodyLogger {
+    pub fn new(max_chars: usize) -> Self {
+        Self {
+            max_chars,
+            secrets: vec![],
+        }
+    }
+
+    /// Returns a logger that additionally redacts `secret`.
+    pub fn with_secret(&self, secret: &str) -> Self {
+        let mut logger = self.clone();
+        if !secret.is_empty() {
+            logger.secrets.push(secret.to_owned());
+        }
+        logger
+    }
+
+    pub fn log_request(&self, target: &str, body: &str) {
+        trace!("{} request body: {}", target, self.sanitize(body));
+    }
+
+    pub fn log_response(&self, target: &str, body: &str) {
+        trace!("{} response body: {}", target, self.sanitize(body));
+    }
+
+    fn sanitize(&self, body: &str) -> String {
+        // Redact before truncating so that a secret cut in half is never logged
+        let mut body = body.to_owned();
+        for secret in self.secrets.iter() {
+            body = body.replace(secret.as_str(), "<redacted>");
+        }
+        truncate_for_log(&body, self.max_chars)
+    }
+}
+
+impl LoggedHttp {
+    pub fn new(inner: Http, body_logger: Option<BodyLogger>) -> Self {
+        Self { inner, body_logger }
+    }
+}
+
+#[async_trait]
+impl JsonRpcClient for LoggedHttp {
+    type Error = HttpClientError;
+
+    async fn request<T, R>(&self, method: &str, params: T) -> Result<R, Self::Error>
+    where
+        T: Debug + Serialize + Send + Sync,
+        R: DeserializeOwned + Send,
+    {
+        let body_logger = match &self.body_logger {
+            Some(body_logger) => body_logger,
+            None => return self.inner.request(method, params).await,
+        };
+
+        let target = format!("JSON-RPC {method}");
+        body_logger.log_request(
+            &target,
+            &serde_json::to_string(&params).unwrap_or_else(|err| err.to_string()),
+        );
+
+        let value: serde_json::Value = self.inner.request(method, params).await?;
+        let text = value.to_string();
+        body_logger.log_response(&target, &text);
+
+        serde_json::from_value(value).map_err(|err| HttpClientError::SerdeJson { err, text })
+    }
+}
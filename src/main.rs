@@ -2,50 +2,166 @@ extern crate hex;
 use hex::encode;
 use std::fmt;
 use std::{
-    collections::{hash_map::Entry, HashMap, HashSet},
-    io::Write,
-    path::PathBuf,
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    future::Future,
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
     sync::Arc,
-    time::{Duration, SystemTime},
+    time::{Duration, Instant, SystemTime},
 };
 
 use anyhow::Result;
-use clap::Parser;
+use clap::{Parser, Subcommand, ValueEnum};
 use dotenv::dotenv;
-use ethers::providers::StreamExt;
 use ethers::{
-    abi::Token,
+    abi::{AbiEncode, Token},
     prelude::*,
     types::transaction::eip712::{EIP712Domain, Eip712},
-    utils::{keccak256, to_checksum},
+    utils::{hash_message, keccak256, to_checksum},
 };
-use log::{debug, error, info};
+use futures::{Stream, TryStreamExt};
+use log::{debug, error, info, warn};
 use reqwest::Url;
 use serde::{Deserialize, Serialize};
+use serde_with::serde_as;
 
 use crate::{
-    config::{RewardConfig, ScheduledReward},
-    contracts::LnRewardSystem,
-    custom_serde::{checksumed_address, hex_bytes, u256_dec},
-    graphql::{DebtEntry, ExchangeEntry, GraphqlClient, PerpFeeEntry, RewardClaim},
-    wallet::{Wallet, WalletConfig},
-    worker::{RewardComposition, WorkerClient},
+    adjustments::{load_adjustments, RewardAdjustment},
+    approval::ApprovalGate,
+    chain_head::ChainHeadSource,
+    claims::{backfill_claims, compute_rollover, ClaimLedger, PoolAmounts, Rollover},
+    config::RewardConfig,
+    contracts::{Blocklist, ClaimRewardForCall, EmissionTracker, Forwarder, LnRewardSystem},
+    custom_serde::{checksumed_address, hex_bytes, u256_dec, u256_dec_vec, ChecksumedAddress},
+    distribution::split_pool,
+    domains::{load_domain_overrides, RewardDomains},
+    graphql::{DebtEntry, ExchangeEntry, GraphqlClient, PerpFeeEntry},
+    http_log::{BodyLogger, LoggedHttp},
+    notify::{CycleSummary, Notifier},
+    rate_limit::RateLimiter,
+    reconciliation::{PoolAllocation, ReconciliationReport},
+    reward::{
+        reward_domain, reward_packed_hash, reward_struct_hash, Eip712RewardBatch,
+        Eip712RewardEntry, RewardEntry, REWARD_BATCH_TYPE, REWARD_TYPE, REWARD_WITH_NONCE_TYPE,
+    },
+    safe::SafeBatch,
+    util::{
+        apply_signed_delta, format_signed_units_for_log, format_units_for_log, full_jitter_backoff,
+        to_canonical_json,
+    },
+    wallet::{Wallet, WalletConfig, WalletError},
+    weights::{
+        check_debt_conservation, into_sorted_weights, validate_weights, RewardWeightStrategy,
+        WeightStrategy,
+    },
+    worker::{
+        reward_config_checksum, PeriodAttestation, RewardComposition, StageOutcome, Submission,
+        SubmissionRewardEntry, WorkerClient, WorkerConfig, WorkerTls, WorkerTlsConfig,
+    },
 };
 
+mod adjustments;
+mod approval;
+mod chain_head;
+mod claims;
 mod config;
 mod contracts;
 mod custom_serde;
+mod distribution;
+mod domains;
+mod gcp_kms;
 mod graphql;
+mod http_log;
+mod notify;
+mod rate_limit;
+mod reconciliation;
+mod reward;
+mod safe;
+mod util;
 mod wallet;
+mod weights;
 mod worker;
 
-#[derive(Debug, Parser)]
+// No `Debug`, as the worker admin token and wallet settings would end up in the output
+#[derive(Parser)]
 #[clap(author, version, about)]
 struct Cli {
     #[clap(long, env = "JSON_RPC", help = "URL of the JSON-RPC interface.")]
     json_rpc: Url,
+    #[clap(
+        long,
+        env = "JSON_RPC_WS",
+        help = "Websocket URL of the JSON-RPC interface to subscribe to new heads (optional). The head is polled over HTTP otherwise."
+    )]
+    json_rpc_ws: Option<Url>,
+    #[clap(
+        long,
+        env = "EXPECTED_CHAIN_ID",
+        help = "Chain id the JSON-RPC node must report (optional)."
+    )]
+    expected_chain_id: Option<u64>,
     #[clap(long, env = "GRAPH_QUERY", help = "GraphQL query URL.")]
     graph_query: Url,
+    #[clap(
+        long,
+        env = "GRAPH_QUERY_FALLBACK",
+        value_delimiter = ',',
+        help = "Backup GraphQL query URLs to fail over to in order when the primary is unreachable or failing (optional)."
+    )]
+    graph_query_fallback: Vec<Url>,
+    #[clap(
+        long,
+        env = "GRAPH_RPS",
+        help = "Maximum number of GraphQL requests per second (optional)."
+    )]
+    graph_rps: Option<f64>,
+    #[clap(
+        long,
+        env = "NO_GRAPH_DEDUP",
+        help = "Keep GraphQL entries with duplicate ids instead of dropping all but the first."
+    )]
+    no_graph_dedup: bool,
+    #[clap(
+        long,
+        env = "GRAPH_BATCH_SIZE",
+        value_parser = clap::value_parser!(u64).range(1..),
+        default_value = "1000",
+        help = "Number of entries to fetch per GraphQL request. Lower this for gateways that cap `first`."
+    )]
+    graph_batch_size: u64,
+    #[clap(
+        long,
+        env = "GRAPH_RETRY_BASE_DELAY_MS",
+        default_value = "500",
+        help = "Upper bound of the delay before the first GraphQL retry in milliseconds. Doubles with every attempt."
+    )]
+    graph_retry_base_delay_ms: u64,
+    #[clap(
+        long,
+        env = "GRAPH_RETRY_MAX_DELAY_SECS",
+        default_value = "30",
+        help = "Longest delay between GraphQL retries in seconds, including delays asked for via Retry-After."
+    )]
+    graph_retry_max_delay_secs: u64,
+    #[clap(
+        long,
+        env = "CHECK_GRAPH_INDEX_GAPS",
+        help = "Fail when fetched GraphQL entries have gaps in their indices. Only for subgraphs that index entries without holes."
+    )]
+    check_graph_index_gaps: bool,
+    #[clap(
+        long,
+        env = "EXPECTED_SCHEMA_HASH",
+        value_parser = parse_sha256_sum,
+        help = "Expected hash of the subgraph schema's type and field names (optional)."
+    )]
+    expected_schema_hash: Option<[u8; 32]>,
+    #[clap(
+        long,
+        env = "WARN_ON_SCHEMA_MISMATCH",
+        help = "Only warn instead of failing when the subgraph schema hash doesn't match."
+    )]
+    warn_on_schema_mismatch: bool,
     #[clap(
         long,
         env = "LEGACY_CHAIN_JSON_RPC",
@@ -59,6 +175,39 @@ struct Cli {
         help = "Address of the LnRewardSystem contract."
     )]
     reward_system_address: Address,
+    #[clap(
+        long,
+        env = "BLOCKLIST_CONTRACT",
+        help = "Address of a contract exposing isBlocked(address) to exclude recipients (optional)."
+    )]
+    blocklist_contract: Option<Address>,
+    #[clap(
+        long,
+        env = "EMISSION_AUDIT_CONTRACT",
+        help = "Address of a contract exposing totalDistributed() to audit the reward schedule against (optional)."
+    )]
+    emission_audit_contract: Option<Address>,
+    #[clap(
+        long,
+        env = "EMISSION_AUDIT_TOLERANCE_BPS",
+        default_value = "100",
+        help = "Allowed divergence between scheduled and distributed rewards in basis points."
+    )]
+    emission_audit_tolerance_bps: u64,
+    #[clap(
+        long,
+        env = "WEIGHT_STRATEGY",
+        value_enum,
+        default_value = "time-weighted",
+        help = "How stakers are weighted for the staking reward split."
+    )]
+    weight_strategy: WeightStrategy,
+    #[clap(
+        long,
+        env = "DEBT_CONSERVATION_TOLERANCE_BPS",
+        help = "Check that time-weighted staker weights add up to the full debt share over the period, allowing this divergence in basis points (optional)."
+    )]
+    debt_conservation_tolerance_bps: Option<u64>,
     #[clap(
         long,
         env = "EIP_712_CONTRACT_NAME",
@@ -66,10 +215,252 @@ struct Cli {
         help = "Contract name for EIP-712 signatures."
     )]
     eip_712_contract_name: String,
+    #[clap(
+        long,
+        env = "EIP_712_NO_CHAIN_ID",
+        help = "Omit chainId from the EIP-712 domain for messages valid on multiple chains."
+    )]
+    eip_712_no_chain_id: bool,
+    #[clap(
+        long,
+        env = "EIP_712_DOMAIN_OVERRIDES_FILE",
+        help = "Path to a JSON file mapping period ranges to the contract name, address and version to sign them against. Other periods use the configured contract."
+    )]
+    eip_712_domain_overrides_file: Option<PathBuf>,
+    #[clap(
+        long,
+        env = "REWARD_SCHEMA",
+        value_enum,
+        default_value = "v1",
+        help = "Version of the signed reward struct. v2 adds the per-recipient nonce."
+    )]
+    reward_schema: RewardSchema,
+    #[clap(
+        long,
+        env = "SIGNING_MODE",
+        value_enum,
+        default_value = "per-entry",
+        help = "Sign each reward entry separately or the whole period at once."
+    )]
+    signing_mode: SigningMode,
+    #[clap(
+        long,
+        env = "SIGNATURE_SCHEME",
+        value_enum,
+        default_value = "eip712",
+        help = "Signature scheme for reward entries. eip191 is only for legacy verifiers."
+    )]
+    signature_scheme: SignatureScheme,
+    #[clap(
+        long,
+        env = "SIGN_PERIOD_ATTESTATION",
+        help = "Additionally sign an attestation over the period's entries and totals."
+    )]
+    sign_period_attestation: bool,
+    #[clap(
+        long,
+        env = "SAFE_BATCH_FILE",
+        help = "Path to write the signed entries to as a Safe Transaction Builder batch of claimRewardFor calls (optional)."
+    )]
+    safe_batch_file: Option<PathBuf>,
+    #[clap(
+        long,
+        env = "FORWARDER_ADDRESS",
+        requires = "forward_requests_file",
+        help = "Address of the meta-transaction forwarder to additionally sign claim requests for (optional)."
+    )]
+    forwarder_address: Option<Address>,
+    #[clap(
+        long,
+        env = "FORWARDER_NAME",
+        default_value = "MinimalForwarder",
+        help = "Name in the EIP-712 domain of the forwarder."
+    )]
+    forwarder_name: String,
+    #[clap(
+        long,
+        env = "FORWARDER_VERSION",
+        default_value = "0.0.1",
+        help = "Version in the EIP-712 domain of the forwarder."
+    )]
+    forwarder_version: String,
+    #[clap(
+        long,
+        env = "FORWARDER_GAS",
+        default_value = "300000",
+        help = "Gas limit of each forwarded claim."
+    )]
+    forwarder_gas: u64,
+    #[clap(
+        long,
+        env = "FORWARD_REQUESTS_FILE",
+        help = "Path to write the signed forward requests to."
+    )]
+    forward_requests_file: Option<PathBuf>,
 
     #[clap(flatten)]
     wallet: WalletConfig,
+    #[clap(
+        long,
+        env = "SIGN_MAX_ATTEMPTS",
+        default_value = "10",
+        value_parser = clap::value_parser!(u32).range(1..),
+        help = "Number of attempts to sign a payload before giving up."
+    )]
+    sign_max_attempts: u32,
+    #[clap(
+        long,
+        env = "SIGN_RETRY_DELAY_MS",
+        default_value = "10000",
+        help = "The duration to wait before retrying a failed signature in milliseconds."
+    )]
+    sign_retry_delay_ms: u64,
+
+    #[clap(long, env = "WORKER_URL", help = "Base URL of the reward worker.")]
+    worker_url: Url,
+    #[clap(
+        long,
+        env = "WORKER_ADMIN_TOKEN",
+        help = "Admin token for the reward worker."
+    )]
+    worker_admin_token: String,
+
+    #[clap(flatten)]
+    worker_tls: WorkerTlsConfig,
+    #[clap(
+        long,
+        env = "WORKER_RPS",
+        help = "Maximum number of worker requests per second (optional)."
+    )]
+    worker_rps: Option<f64>,
+    #[clap(
+        long,
+        env = "REWARD_CONFIG_CHECKSUM",
+        value_parser = parse_sha256_sum,
+        help = "SHA-256 of the approved reward config. The config served by the worker must match it."
+    )]
+    reward_config_checksum: Option<[u8; 32]>,
+    #[clap(
+        long,
+        env = "SIGNATURE_THRESHOLD",
+        help = "Signature threshold to assume when the worker doesn't report one (optional)."
+    )]
+    signature_threshold: Option<u32>,
+
+    #[clap(
+        long,
+        env = "DELEGATION_FILE",
+        help = "Path to a JSON file mapping staker addresses to delegate reward recipients."
+    )]
+    delegation_file: Option<PathBuf>,
+    #[clap(
+        long,
+        env = "ADJUSTMENTS_FILE",
+        help = "Path to a JSON file with manual reward adjustments for the processed period (optional)."
+    )]
+    adjustments_file: Option<PathBuf>,
+    #[clap(
+        long,
+        env = "RESUME_FROM",
+        help = "Path to a partial output file of an interrupted run whose signatures are reused (optional)."
+    )]
+    resume_from: Option<PathBuf>,
+    #[clap(
+        long,
+        env = "OUTPUT",
+        help = "Path to write the signed reward entries, or the signed batch in batch mode, to as JSON, or - for stdout (optional)."
+    )]
+    output: Option<PathBuf>,
+    #[clap(
+        long,
+        env = "ALLOWLIST_FILE",
+        help = "Path to a JSON array of the only recipients allowed to be signed for (optional)."
+    )]
+    allowlist_file: Option<PathBuf>,
+    #[clap(
+        long,
+        env = "SUBMIT",
+        help = "Stage the signed entries of the period with the worker. Only supported in per-entry mode."
+    )]
+    submit: bool,
+    #[clap(
+        long,
+        env = "ALLOW_EMPTY_PERIOD",
+        help = "Allow staging a submission without any reward entries."
+    )]
+    allow_empty_period: bool,
+    #[clap(
+        long,
+        env = "APPROVAL_FILE",
+        help = "Path to the approval of the computed entries. When set, entries are only signed after an authorized approver signed the manifest hash."
+    )]
+    approval_file: Option<PathBuf>,
+    #[clap(
+        long,
+        env = "APPROVAL_MANIFEST_FILE",
+        help = "Path to write the computed entries and their manifest hash to for approval."
+    )]
+    approval_manifest_file: Option<PathBuf>,
+    #[clap(
+        long,
+        env = "APPROVERS",
+        value_delimiter = ',',
+        help = "Addresses allowed to approve manifests, comma separated."
+    )]
+    approvers: Vec<Address>,
 
+    #[clap(
+        long,
+        env = "ANCHOR_CONFIRMATIONS",
+        default_value = "0",
+        help = "Minimum number of blocks the GraphQL anchor block must be behind the chain head."
+    )]
+    anchor_confirmations: u64,
+    #[clap(
+        long,
+        env = "STARTUP_TIMEOUT",
+        default_value = "30",
+        help = "Timeout for each startup call to the node or key store in seconds."
+    )]
+    startup_timeout: u64,
+    #[clap(
+        long,
+        env = "RETRY_STARTUP",
+        help = "Retry failed startup checks with backoff instead of exiting."
+    )]
+    retry_startup: bool,
+    #[clap(
+        long,
+        env = "NOTIFY_WEBHOOK",
+        help = "URL to post a summary of each processing cycle to (optional). Failures are always sent."
+    )]
+    notify_webhook: Option<Url>,
+    #[clap(
+        long,
+        env = "NOTIFY_ON_SUCCESS",
+        help = "Also post a summary of successful cycles to the webhook."
+    )]
+    notify_on_success: bool,
+    #[clap(
+        long,
+        env = "LOG_HTTP_BODIES",
+        help = "Log HTTP request and response bodies at trace level. Secrets are redacted."
+    )]
+    log_http_bodies: bool,
+    #[clap(
+        long,
+        env = "LOG_HTTP_BODY_LIMIT",
+        default_value = "4096",
+        help = "Maximum number of characters logged per HTTP body."
+    )]
+    log_http_body_limit: usize,
+    #[clap(
+        long,
+        env = "CONFIG_CACHE_TTL",
+        default_value = "0",
+        help = "How long fetched worker and reward configs are reused in seconds."
+    )]
+    config_cache_ttl: u64,
     #[clap(
         long,
         env = "PROCESS_INTERVAL",
@@ -77,37 +468,284 @@ struct Cli {
         help = "The duration to pause between processing runs in milliseconds."
     )]
     process_interval: u64,
+    #[clap(
+        long,
+        env = "ONCE",
+        help = "Run a single processing cycle and exit instead of looping. Exits with an error if the cycle fails."
+    )]
+    once: bool,
+    #[clap(
+        long,
+        env = "DISPLAY_DECIMALS",
+        default_value = "18",
+        value_parser = clap::value_parser!(u32).range(0..=77),
+        help = "Decimal places used when logging reward amounts. Serialized output is always in wei."
+    )]
+    display_decimals: u32,
+    #[clap(
+        long,
+        env = "RECONCILIATION_REPORT_FILE",
+        help = "Path to write the reconciliation of allocated and expected period totals to as JSON (optional)."
+    )]
+    reconciliation_report_file: Option<PathBuf>,
+    #[clap(
+        long,
+        env = "CLAIMS_FILE",
+        help = "Path to the claims file the claimed and distributed totals per period are kept in across restarts (optional). Created if it doesn't exist."
+    )]
+    claims_file: Option<PathBuf>,
+    #[clap(
+        long,
+        env = "AUDIT_MATH",
+        help = "Recompute every reward split through an independent fixed-point path and fail the cycle if they disagree."
+    )]
+    audit_math: bool,
+
+    #[clap(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    #[clap(about = "Fetch new reward claims into the local cumulative claims file.")]
+    BackfillClaims {
+        #[clap(
+            long,
+            env = "CLAIMS_FILE",
+            help = "Path to the claims file. Created if it doesn't exist."
+        )]
+        claims_file: PathBuf,
+    },
+    #[clap(about = "Stage a previously saved submission without signing again.")]
+    Replay {
+        #[clap(long, help = "Path to the saved submission JSON file.")]
+        submission_file: PathBuf,
+    },
+    #[clap(
+        about = "Run a full cycle against a forked chain with an ephemeral key, without contacting the worker."
+    )]
+    Simulate {
+        #[clap(
+            long,
+            help = "URL of the JSON-RPC interface of the fork (e.g. Anvil or Hardhat)."
+        )]
+        fork_json_rpc: Url,
+        #[clap(long, help = "Path to the reward config JSON to simulate.")]
+        reward_config_file: PathBuf,
+        #[clap(long, help = "Path to the worker config JSON to simulate.")]
+        worker_config_file: PathBuf,
+    },
+    #[clap(flatten)]
+    Standalone(StandaloneCommand),
+    #[clap(about = "Sign reward entries from a file instead of computing them.")]
+    Sign {
+        #[clap(long, alias = "input", help = "Path to the reward entries to sign.")]
+        input_file: PathBuf,
+        #[clap(
+            long,
+            value_enum,
+            default_value = "auto",
+            help = "Format of the input file. auto detects a JSON array by its leading bracket."
+        )]
+        input_format: InputFormat,
+        #[clap(
+            long,
+            help = "Path to write the signed entries to, in the same format as the input. Keys are sorted for reproducible output."
+        )]
+        output_file: PathBuf,
+        #[clap(
+            long,
+            help = "Skip periods that fail to sign instead of stopping, and fail at the end if any did."
+        )]
+        continue_on_error: bool,
+    },
+}
+
+/// Parser for the commands that don't need any connection settings.
+#[derive(Debug, Parser)]
+struct StandaloneCli {
+    #[clap(subcommand)]
+    command: StandaloneCommand,
+}
+
+#[derive(Debug, Subcommand)]
+enum StandaloneCommand {
+    #[clap(about = "Print build information and the compiled-in EIP-712 type strings.")]
+    Version,
+    #[clap(about = "Inspect reward config files.")]
+    Config {
+        #[clap(subcommand)]
+        command: ConfigCommand,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum ConfigCommand {
+    #[clap(
+        about = "Compute the SHA-256 checksum of a reward config file as the worker does.",
+        long_about = "Compute the SHA-256 checksum of a reward config file as the worker does. \
+            The checksum is taken over the raw file contents, so any change in whitespace or \
+            key order yields a different checksum even if the JSON is equivalent."
+    )]
+    Checksum {
+        #[clap(long, help = "Path to the reward config file.")]
+        file: PathBuf,
+        #[clap(
+            long,
+            value_parser = parse_sha256_sum,
+            help = "Checksum to compare against. Fails on mismatch (optional)."
+        )]
+        expected: Option<[u8; 32]>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum InputFormat {
+    Auto,
+    Json,
+    JsonLines,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum RewardSchema {
+    V1,
+    V2,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum SigningMode {
+    PerEntry,
+    Batch,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum SignatureScheme {
+    Eip191,
+    Eip712,
 }
 
 struct RunContext {
     chain_id: u64,
     signer: Wallet,
-    eip_712_contract_name: String,
-    reward_system_address: Address,
+    /// Second key signing alongside `signer` while a key rotation overlaps.
+    secondary_signer: Option<Wallet>,
+    reward_domains: RewardDomains,
+    reward_schema: RewardSchema,
+    signing_mode: SigningMode,
+    signature_scheme: SignatureScheme,
+    sign_period_attestation: bool,
+    safe_batch_file: Option<PathBuf>,
+    meta_tx: Option<MetaTxConfig>,
+    reward_system: LnRewardSystem<Provider<LoggedHttp>>,
+    chain_head: ChainHeadSource,
+    blocklist: Option<Blocklist<Provider<LoggedHttp>>>,
+    emission_tracker: Option<EmissionTracker<Provider<LoggedHttp>>>,
+    emission_audit_tolerance_bps: u64,
+    weight_strategy: Box<dyn RewardWeightStrategy + Send + Sync>,
+    debt_conservation_tolerance_bps: Option<u64>,
+    /// Kept across cycles so its connection pool and rate limiter are reused. Re-anchored at the
+    /// start of every cycle.
+    graphql_client: GraphqlClient,
+    anchor_confirmations: u64,
+    worker_client: WorkerClient,
+    /// Operator-approved checksum the reward config from the worker is verified against.
+    reward_config_checksum: Option<[u8; 32]>,
+    delegations: HashMap<Address, Address>,
+    adjustments: Vec<RewardAdjustment>,
+    allowlist: Option<HashSet<Address>>,
+    resumed_entries: HashMap<(u32, Address), SignedRewardEntry>,
+    output: Option<PathBuf>,
+    sign_retry: SignRetry,
+    submit: bool,
+    allow_empty_period: bool,
+    approval: Option<ApprovalGate>,
+    signature_threshold: Option<u32>,
+    simulation: bool,
+    display_decimals: u32,
+    reconciliation_report_file: Option<PathBuf>,
+    /// Updated incrementally every cycle. Only kept in memory if `claims_file` is unset.
+    claim_ledger: tokio::sync::Mutex<ClaimLedger>,
+    claims_file: Option<PathBuf>,
+    audit_math: bool,
 }
 
-#[derive(PartialEq, Eq, Serialize, Deserialize)]
+struct CycleConfig {
+    worker_config: WorkerConfig,
+    reward_config: RewardConfig,
+    reward_config_checksum: [u8; 32],
+}
+
+/// Forwarder through which the relayer submits claims as meta-transactions.
+struct MetaTxConfig {
+    forwarder: Forwarder<Provider<LoggedHttp>>,
+    domain: EIP712Domain,
+    gas: U256,
+    output_file: PathBuf,
+}
+
+#[derive(Serialize)]
 #[serde(rename_all = "camelCase")]
-struct RewardEntry {
-    chain_id: u64,
-    period_id: u32,
+struct ForwardRequest {
     #[serde(serialize_with = "checksumed_address::serialize")]
-    recipient: Address,
+    from: Address,
+    #[serde(serialize_with = "checksumed_address::serialize")]
+    to: Address,
+    #[serde(with = "u256_dec")]
+    value: U256,
     #[serde(with = "u256_dec")]
-    staking_reward: U256,
+    gas: U256,
     #[serde(with = "u256_dec")]
-    fee_reward: U256,
+    nonce: U256,
+    #[serde(with = "hex_bytes")]
+    data: Vec<u8>,
 }
 
-#[derive(PartialEq, Eq, Serialize, Deserialize)]
-struct TraceEntry {
-    #[serde(serialize_with = "checksumed_address::serialize")]
-    address: Address,
-    #[serde(with = "u256_dec")]
-    weight: U256,
+/// EIP-712 encoding of a [ForwardRequest] under the forwarder's domain.
+struct Eip712ForwardRequest<'a> {
+    inner: &'a ForwardRequest,
+    domain: &'a EIP712Domain,
 }
 
-#[derive(PartialEq, Eq, Serialize, Deserialize)]
+impl<'a> Eip712 for Eip712ForwardRequest<'a> {
+    type Error = std::convert::Infallible;
+
+    fn domain(&self) -> std::result::Result<EIP712Domain, Self::Error> {
+        Ok(self.domain.clone())
+    }
+
+    fn type_hash() -> std::result::Result<[u8; 32], Self::Error> {
+        Ok(keccak256(FORWARD_REQUEST_TYPE))
+    }
+
+    fn struct_hash(&self) -> std::result::Result<[u8; 32], Self::Error> {
+        Ok(keccak256(abi::encode(&[
+            Token::Uint(U256::from(Self::type_hash()?)),
+            Token::Address(self.inner.from),
+            Token::Address(self.inner.to),
+            Token::Uint(self.inner.value),
+            Token::Uint(self.inner.gas),
+            Token::Uint(self.inner.nonce),
+            Token::FixedBytes(keccak256(&self.inner.data).to_vec()),
+        ])))
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SignedForwardRequest {
+    request: ForwardRequest,
+    #[serde(with = "hex_bytes")]
+    signature: Vec<u8>,
+}
+
+/// Keeps the last successfully fetched [CycleConfig] for reuse within `ttl` and as a fallback when
+/// the worker is briefly unavailable.
+struct ConfigCache {
+    ttl: Duration,
+    entry: Option<(CycleConfig, Instant)>,
+}
+
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 struct SignedRewardEntry {
     #[serde(flatten)]
@@ -115,8 +753,25 @@ struct SignedRewardEntry {
     signatures: Vec<Signature>,
 }
 
+/// A signed batch in the layout of a batch claim, which takes each field of the entries as an array
+/// in entry order.
+#[serde_as]
 #[derive(PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
+struct SignedRewardBatch {
+    #[serde_as(as = "Vec<ChecksumedAddress>")]
+    recipients: Vec<Address>,
+    #[serde(with = "u256_dec_vec")]
+    staking_rewards: Vec<U256>,
+    #[serde(with = "u256_dec_vec")]
+    fee_rewards: Vec<U256>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty", with = "u256_dec_vec")]
+    nonces: Vec<U256>,
+    signature: Signature,
+}
+
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 struct Signature {
     #[serde(serialize_with = "checksumed_address::serialize")]
     signer: Address,
@@ -124,30 +779,6 @@ struct Signature {
     signature: Vec<u8>,
 }
 
-impl PartialOrd for RewardEntry {
-    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        self.recipient.partial_cmp(&other.recipient)
-    }
-}
-
-impl Ord for RewardEntry {
-    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        self.recipient.cmp(&other.recipient)
-    }
-}
-
-impl PartialOrd for TraceEntry {
-    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        self.address.partial_cmp(&other.address)
-    }
-}
-
-impl Ord for TraceEntry {
-    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        self.address.cmp(&other.address)
-    }
-}
-
 trait PoolableFeeEntry {
     fn fee_for_pool(&self) -> U256;
 
@@ -188,158 +819,2252 @@ async fn main() -> Result<()> {
 
     env_logger::init();
 
+    // Standalone commands must work without any of the otherwise required settings
+    if let Ok(standalone_cli) = StandaloneCli::try_parse() {
+        return run_standalone(&standalone_cli.command);
+    }
+
     let cli = Cli::parse();
 
-    debug!("Collecting settings from contract via JSON-RPC...");
-    let rpc_provider = Arc::new(Provider::new(Http::new_with_client(
-        cli.json_rpc.clone(),
-        reqwest::ClientBuilder::new()
-            .timeout(Duration::from_secs(10))
-            .build()
-            .unwrap(),
-    )));
-    let chain_id = rpc_provider.get_chainid().await?.as_u64();
-    info!("Chain Id: {}", chain_id);
+    if let Some(Command::Standalone(command)) = &cli.command {
+        return run_standalone(command);
+    }
 
-    let signer = Wallet::from_source(&cli.wallet, chain_id).await?;
-    info!("Reward signer: {}", to_checksum(&signer.address(), None));
+    if cli.signature_scheme == SignatureScheme::Eip191
+        && (cli.signing_mode != SigningMode::PerEntry || cli.reward_schema != RewardSchema::V1)
+    {
+        anyhow::bail!("eip191 signatures only support per-entry signing with the v1 schema");
+    }
 
-    info!(
-        "Reward System: {}",
-        to_checksum(&cli.reward_system_address, None)
+    if cli.submit && cli.signing_mode != SigningMode::PerEntry {
+        anyhow::bail!("submitting to the worker is only supported in per-entry mode");
+    }
+
+    if (cli.safe_batch_file.is_some() || cli.forwarder_address.is_some())
+        && (cli.signing_mode != SigningMode::PerEntry || cli.reward_schema != RewardSchema::V1)
+    {
+        anyhow::bail!("claim calldata exports only support per-entry signing with the v1 schema");
+    }
+
+    if cli.debt_conservation_tolerance_bps.is_some()
+        && cli.weight_strategy != WeightStrategy::TimeWeighted
+    {
+        anyhow::bail!("debt conservation can only be checked with time-weighted weights");
+    }
+
+    let body_logger = cli
+        .log_http_bodies
+        .then(|| BodyLogger::new(cli.log_http_body_limit));
+
+    debug!("Collecting settings from contract via JSON-RPC...");
+    let rpc_provider = Arc::new(Provider::new(LoggedHttp::new(
+        Http::new_with_client(
+            cli.json_rpc.clone(),
+            reqwest::ClientBuilder::new()
+                .timeout(Duration::from_secs(10))
+                .build()
+                .unwrap(),
+        ),
+        body_logger.clone(),
+    )));
+    let startup_timeout = Duration::from_secs(cli.startup_timeout);
+    let chain_head = match &cli.json_rpc_ws {
+        Some(ws_url) => ChainHeadSource::subscribe(ws_url.clone()),
+        None => ChainHeadSource::polling(),
+    };
+
+    if let Some(expected_schema_hash) = &cli.expected_schema_hash {
+        // The introspection query isn't anchored to a block
+        let graphql_client = graphql_client(&cli, 0, body_logger.clone());
+        let schema_hash = with_startup_timeout(
+            startup_timeout,
+            "fetching subgraph schema",
+            graphql_client.get_schema_hash(),
+        )
+        .await?;
+
+        if &schema_hash != expected_schema_hash {
+            let message = format!(
+                "subgraph schema hash mismatch: expected: 0x{}; actual: 0x{}",
+                hex::encode(expected_schema_hash),
+                hex::encode(schema_hash)
+            );
+            if cli.warn_on_schema_mismatch {
+                warn!("{}", message);
+            } else {
+                anyhow::bail!(message);
+            }
+        } else {
+            info!("Subgraph schema hash: 0x{}", hex::encode(schema_hash));
+        }
+    }
+
+    if let Some(Command::BackfillClaims { claims_file }) = &cli.command {
+        let anchor_block = with_startup_timeout(
+            startup_timeout,
+            "fetching block number",
+            select_anchor_block(&chain_head, &rpc_provider, cli.anchor_confirmations),
+        )
+        .await?;
+        let graphql_client = graphql_client(&cli, anchor_block, body_logger.clone());
+
+        with_startup_timeout(
+            startup_timeout,
+            "checking subgraph sync",
+            graphql_client.ensure_synced(),
+        )
+        .await?;
+
+        return backfill_claims(&graphql_client, claims_file).await;
+    }
+
+    if let Some(Command::Simulate {
+        fork_json_rpc,
+        reward_config_file,
+        worker_config_file,
+    }) = &cli.command
+    {
+        return simulate(
+            &cli,
+            fork_json_rpc,
+            reward_config_file,
+            worker_config_file,
+            &body_logger,
+        )
+        .await;
+    }
+
+    let run_context = if cli.retry_startup {
+        let mut attempt = 0;
+        loop {
+            match init_run_context(&cli, &rpc_provider, &chain_head, &body_logger, false).await {
+                Ok(run_context) => break run_context,
+                Err(err) => {
+                    let delay = full_jitter_backoff(
+                        Duration::from_secs(STARTUP_RETRY_BASE_SECS),
+                        Duration::from_secs(STARTUP_RETRY_MAX_SECS),
+                        attempt,
+                    );
+                    warn!(
+                        "Startup failed: {err}; retrying in {} ms",
+                        delay.as_millis()
+                    );
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+            }
+        }
+    } else {
+        init_run_context(&cli, &rpc_provider, &chain_head, &body_logger, false).await?
+    };
+
+    if let Some(Command::Replay { submission_file }) = &cli.command {
+        return replay_submission(&run_context, submission_file).await;
+    }
+    if let Some(Command::Sign {
+        input_file,
+        input_format,
+        output_file,
+        continue_on_error,
+    }) = &cli.command
+    {
+        return sign_input_file(
+            &run_context,
+            input_file,
+            *input_format,
+            output_file,
+            *continue_on_error,
+        )
+        .await;
+    }
+
+    let notifier = cli
+        .notify_webhook
+        .map(|url| Notifier::new(url, Duration::from_secs(5), cli.notify_on_success));
+
+    let mut config_cache = ConfigCache::new(Duration::from_secs(cli.config_cache_ttl));
+    let mut last_worker_config = None;
+    let mut last_reward_config_checksum = None;
+
+    // Spawned right away so that the handlers are installed before the first cycle starts
+    let mut shutdown = tokio::spawn(shutdown_signal());
+
+    loop {
+        let result = match config_cache.get(&run_context).await {
+            Ok(cycle_config) => {
+                if last_reward_config_checksum != Some(cycle_config.reward_config_checksum) {
+                    info!(
+                        "Reward config checksum: 0x{}",
+                        hex::encode(cycle_config.reward_config_checksum)
+                    );
+                    last_reward_config_checksum = Some(cycle_config.reward_config_checksum);
+                }
+                if last_worker_config.as_ref() != Some(&cycle_config.worker_config) {
+                    info!("Worker config: {:?}", cycle_config.worker_config);
+                    last_worker_config = Some(cycle_config.worker_config.clone());
+                }
+
+                run_once(&run_context, cycle_config).await
+            }
+            Err(err) => Err(err),
+        };
+
+        if let Some(notifier) = &notifier {
+            notifier.notify(&result).await;
+        }
+
+        if cli.once {
+            return result.map(|_| ());
+        }
+
+        if let Err(err) = result {
+            error!("Error: {err}");
+        }
+
+        tokio::select! {
+            _ = tokio::time::sleep(Duration::from_millis(cli.process_interval)) => {}
+            result = &mut shutdown => {
+                result??;
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// Resolves once SIGINT or SIGTERM is received.
+async fn shutdown_signal() -> Result<()> {
+    #[cfg(unix)]
+    {
+        let mut sigterm =
+            tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())?;
+        tokio::select! {
+            result = tokio::signal::ctrl_c() => result?,
+            _ = sigterm.recv() => {}
+        }
+    }
+    #[cfg(not(unix))]
+    tokio::signal::ctrl_c().await?;
+
+    info!("Shutdown requested, finishing current run");
+
+    Ok(())
+}
+
+/// Connects to the node and key store and loads everything a run needs. A `simulation` uses a random
+/// key instead of the configured key store and never contacts the worker during the cycle.
+async fn init_run_context(
+    cli: &Cli,
+    rpc_provider: &Arc<Provider<LoggedHttp>>,
+    chain_head: &ChainHeadSource,
+    body_logger: &Option<BodyLogger>,
+    simulation: bool,
+) -> Result<RunContext> {
+    let startup_timeout = Duration::from_secs(cli.startup_timeout);
+
+    let chain_id = with_startup_timeout(
+        startup_timeout,
+        "fetching chain id",
+        fetch_chain_id(rpc_provider, cli.expected_chain_id),
+    )
+    .await?;
+    info!("Chain Id: {}", chain_id);
+
+    let signer = if simulation {
+        Wallet::LocalWallet(LocalWallet::new(&mut rand::thread_rng())).with_chain_id(chain_id)
+    } else {
+        with_startup_timeout(
+            startup_timeout,
+            "loading signer",
+            Wallet::from_source(&cli.wallet, chain_id),
+        )
+        .await?
+    };
+    info!("Reward signer: {}", to_checksum(&signer.address(), None));
+
+    let secondary_signer = if simulation {
+        None
+    } else {
+        with_startup_timeout(
+            startup_timeout,
+            "loading secondary signer",
+            Wallet::secondary_from_source(&cli.wallet, chain_id),
+        )
+        .await?
+    };
+    if let Some(secondary_signer) = &secondary_signer {
+        if secondary_signer.address() == signer.address() {
+            anyhow::bail!("secondary signer is the same key as the primary signer");
+        }
+        info!(
+            "Secondary reward signer: {}",
+            to_checksum(&secondary_signer.address(), None)
+        );
+    }
+
+    info!(
+        "Reward System: {}",
+        to_checksum(&cli.reward_system_address, None)
+    );
+
+    let worker_tls = WorkerTls::from_config(&cli.worker_tls)?;
+
+    let delegations = match &cli.delegation_file {
+        Some(path) => {
+            let delegations: HashMap<Address, Address> =
+                serde_json::from_slice(&std::fs::read(path)?)?;
+            info!("Loaded {} delegations", delegations.len());
+            delegations
+        }
+        None => HashMap::new(),
+    };
+
+    let adjustments = match &cli.adjustments_file {
+        Some(path) => {
+            let adjustments = load_adjustments(path)?;
+            info!("Loaded {} reward adjustments", adjustments.len());
+            adjustments
+        }
+        None => vec![],
+    };
+
+    let allowlist = match &cli.allowlist_file {
+        Some(path) => {
+            let allowlist: HashSet<Address> = serde_json::from_slice(&std::fs::read(path)?)?;
+            info!("Loaded {} allowlisted recipients", allowlist.len());
+            Some(allowlist)
+        }
+        None => None,
+    };
+
+    let resumed_entries = match &cli.resume_from {
+        Some(path) => {
+            let resumed_entries = load_signed_entries(path)?
+                .into_iter()
+                .map(|entry| ((entry.reward.period_id, entry.reward.recipient), entry))
+                .collect::<HashMap<_, _>>();
+            info!("Loaded {} already signed entries", resumed_entries.len());
+            resumed_entries
+        }
+        None => HashMap::new(),
+    };
+
+    let approval = match (&cli.approval_file, &cli.approval_manifest_file) {
+        (Some(approval_file), Some(manifest_file)) => {
+            if cli.approvers.is_empty() {
+                anyhow::bail!("approval requires at least one approver");
+            }
+            Some(ApprovalGate::new(
+                cli.approvers.iter().cloned().collect(),
+                manifest_file.clone(),
+                approval_file.clone(),
+            ))
+        }
+        (None, None) => None,
+        _ => anyhow::bail!("approval file and approval manifest file must be set together"),
+    };
+
+    let domain_overrides = match &cli.eip_712_domain_overrides_file {
+        Some(path) => {
+            let domain_overrides = load_domain_overrides(path)?;
+            info!("Loaded {} EIP-712 domain overrides", domain_overrides.len());
+            domain_overrides
+        }
+        None => vec![],
+    };
+
+    let run_context = RunContext {
+        chain_id,
+        signer,
+        secondary_signer,
+        reward_domains: RewardDomains::new(
+            reward_domain(
+                if cli.eip_712_no_chain_id {
+                    None
+                } else {
+                    Some(chain_id)
+                },
+                &cli.eip_712_contract_name,
+                cli.reward_system_address,
+            ),
+            domain_overrides,
+        )?,
+        reward_schema: cli.reward_schema,
+        signing_mode: cli.signing_mode,
+        signature_scheme: cli.signature_scheme,
+        sign_period_attestation: cli.sign_period_attestation,
+        safe_batch_file: cli.safe_batch_file.clone(),
+        meta_tx: match (cli.forwarder_address, &cli.forward_requests_file) {
+            (Some(forwarder_address), Some(output_file)) => Some(MetaTxConfig {
+                forwarder: Forwarder::new(forwarder_address, rpc_provider.clone()),
+                domain: EIP712Domain {
+                    name: Some(cli.forwarder_name.clone()),
+                    version: Some(cli.forwarder_version.clone()),
+                    chain_id: Some(chain_id.into()),
+                    verifying_contract: Some(forwarder_address),
+                    salt: None,
+                },
+                gas: cli.forwarder_gas.into(),
+                output_file: output_file.clone(),
+            }),
+            _ => None,
+        },
+        reward_system: LnRewardSystem::new(cli.reward_system_address, rpc_provider.clone()),
+        chain_head: chain_head.clone(),
+        blocklist: cli
+            .blocklist_contract
+            .map(|address| Blocklist::new(address, rpc_provider.clone())),
+        emission_tracker: cli
+            .emission_audit_contract
+            .map(|address| EmissionTracker::new(address, rpc_provider.clone())),
+        emission_audit_tolerance_bps: cli.emission_audit_tolerance_bps,
+        weight_strategy: cli.weight_strategy.build(),
+        debt_conservation_tolerance_bps: cli.debt_conservation_tolerance_bps,
+        graphql_client: graphql_client(cli, 0, body_logger.clone()),
+        anchor_confirmations: cli.anchor_confirmations,
+        worker_client: WorkerClient::new(
+            cli.worker_url.clone(),
+            cli.worker_admin_token.clone(),
+            Duration::from_secs(10),
+            &worker_tls,
+            cli.worker_rps.map(|rps| Arc::new(RateLimiter::new(rps))),
+            body_logger.as_ref(),
+        ),
+        reward_config_checksum: cli.reward_config_checksum,
+        delegations,
+        adjustments,
+        allowlist,
+        resumed_entries,
+        output: cli.output.clone(),
+        sign_retry: SignRetry {
+            max_attempts: cli.sign_max_attempts,
+            delay: Duration::from_millis(cli.sign_retry_delay_ms),
+        },
+        submit: cli.submit,
+        allow_empty_period: cli.allow_empty_period,
+        approval,
+        signature_threshold: cli.signature_threshold,
+        simulation,
+        display_decimals: cli.display_decimals,
+        reconciliation_report_file: cli.reconciliation_report_file.clone(),
+        claim_ledger: tokio::sync::Mutex::new(match &cli.claims_file {
+            Some(claims_file) => ClaimLedger::load(claims_file)?,
+            None => ClaimLedger::default(),
+        }),
+        claims_file: cli.claims_file.clone(),
+        audit_math: cli.audit_math,
+    };
+
+    // Signatures of a key the contract doesn't know would make every claim fail
+    if run_context.secondary_signer.is_some() {
+        let reward_signers = with_startup_timeout(
+            startup_timeout,
+            "fetching reward signers",
+            fetch_reward_signers(&run_context.reward_system),
+        )
+        .await?;
+        for signer in run_context.signers() {
+            if !reward_signers.contains(&signer.address()) {
+                anyhow::bail!(
+                    "signer {} is not a reward signer on-chain",
+                    to_checksum(&signer.address(), None)
+                );
+            }
+        }
+    }
+
+    // The contract can't tell us whether it expects chainId in its domain, so make sure a
+    // chainId-free domain at least yields the same separator before signing anything with it
+    if cli.eip_712_no_chain_id {
+        let contract_separator =
+            with_startup_timeout(startup_timeout, "fetching domain separator", async {
+                Ok(run_context.reward_system.domain_separator().call().await?)
+            })
+            .await?;
+        if contract_separator != run_context.reward_domains.default_domain().separator() {
+            anyhow::bail!(
+                "domain separator without chain id does not match contract (0x{})",
+                hex::encode(contract_separator)
+            );
+        }
+    }
+
+    Ok(run_context)
+}
+
+/// Fetches the chain id of the node and checks it with [validate_chain_id], as it flows into every
+/// signature.
+async fn fetch_chain_id<M>(provider: &M, expected_chain_id: Option<u64>) -> Result<u64>
+where
+    M: Middleware + 'static,
+{
+    let chain_id = provider.get_chainid().await?.as_u64();
+    validate_chain_id(chain_id, expected_chain_id)?;
+
+    Ok(chain_id)
+}
+
+fn validate_chain_id(chain_id: u64, expected_chain_id: Option<u64>) -> Result<()> {
+    if chain_id == 0 {
+        anyhow::bail!("node reported chain id 0");
+    }
+    if let Some(expected_chain_id) = expected_chain_id {
+        if chain_id != expected_chain_id {
+            anyhow::bail!(
+                "node reported chain id {} but {} is expected",
+                chain_id,
+                expected_chain_id
+            );
+        }
+    }
+
+    Ok(())
+}
+
+fn run_standalone(command: &StandaloneCommand) -> Result<()> {
+    match command {
+        StandaloneCommand::Version => print_build_info(),
+        StandaloneCommand::Config {
+            command: ConfigCommand::Checksum { file, expected },
+        } => {
+            let checksum = reward_config_checksum(&std::fs::read_to_string(file)?);
+            println!("0x{}", hex::encode(checksum));
+
+            if let Some(expected) = expected {
+                if &checksum != expected {
+                    anyhow::bail!(
+                        "config checksum mismatch: expected: 0x{}",
+                        hex::encode(expected)
+                    );
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn print_build_info() {
+    println!("Version: {}", env!("CARGO_PKG_VERSION"));
+    println!("Commit: {}", env!("GIT_COMMIT_HASH"));
+    println!("Build timestamp: {}", env!("BUILD_TIMESTAMP"));
+
+    for type_string in [
+        REWARD_TYPE,
+        REWARD_WITH_NONCE_TYPE,
+        &format!("{REWARD_BATCH_TYPE}{REWARD_TYPE}"),
+        PERIOD_ATTESTATION_TYPE,
+        FORWARD_REQUEST_TYPE,
+    ] {
+        println!("Type: {}", type_string);
+        println!("Type hash: 0x{}", hex::encode(keccak256(type_string)));
+    }
+}
+
+fn graph_endpoints(cli: &Cli) -> Vec<Url> {
+    std::iter::once(cli.graph_query.clone())
+        .chain(cli.graph_query_fallback.iter().cloned())
+        .collect()
+}
+
+fn graphql_client(cli: &Cli, anchor_block: u64, body_logger: Option<BodyLogger>) -> GraphqlClient {
+    GraphqlClient::with_endpoints(
+        graph_endpoints(cli),
+        anchor_block,
+        Duration::from_secs(30),
+        cli.graph_rps.map(|rps| Arc::new(RateLimiter::new(rps))),
+        body_logger,
+        !cli.no_graph_dedup,
+        cli.graph_batch_size as usize,
+    )
+    .with_index_gap_check(cli.check_graph_index_gaps)
+    .with_retry_delays(
+        Duration::from_millis(cli.graph_retry_base_delay_ms),
+        Duration::from_secs(cli.graph_retry_max_delay_secs),
+    )
+}
+
+async fn with_startup_timeout<T, F>(timeout: Duration, action: &str, future: F) -> Result<T>
+where
+    F: Future<Output = Result<T>>,
+{
+    match tokio::time::timeout(timeout, future).await {
+        Ok(result) => result,
+        Err(_) => anyhow::bail!("timed out after {} seconds {}", timeout.as_secs(), action),
+    }
+}
+
+/// Picks the block `confirmations` blocks behind the current head as the GraphQL anchor so that
+/// queries don't observe blocks that may still be reorged.
+async fn select_anchor_block<M>(
+    chain_head: &ChainHeadSource,
+    provider: &M,
+    confirmations: u64,
+) -> Result<u64>
+where
+    M: Middleware,
+    M::Error: 'static,
+{
+    let head = chain_head.head(provider).await?.number;
+    let anchor_block = head.checked_sub(confirmations).ok_or_else(|| {
+        anyhow::anyhow!(
+            "chain head {} has fewer than {} confirmations",
+            head,
+            confirmations
+        )
+    })?;
+
+    info!("Chain head: {}; anchor block: {}", head, anchor_block);
+
+    Ok(anchor_block)
+}
+
+/// Runs a cycle with on-chain reads against `fork_json_rpc` and configs from local files, so that a
+/// config change can be checked end to end before it's applied. The worker is never contacted.
+async fn simulate(
+    cli: &Cli,
+    fork_json_rpc: &Url,
+    reward_config_file: &Path,
+    worker_config_file: &Path,
+    body_logger: &Option<BodyLogger>,
+) -> Result<()> {
+    let fork_provider = Arc::new(Provider::new(LoggedHttp::new(
+        Http::new_with_client(
+            fork_json_rpc.clone(),
+            reqwest::ClientBuilder::new()
+                .timeout(Duration::from_secs(10))
+                .build()
+                .unwrap(),
+        ),
+        body_logger.clone(),
+    )));
+    let run_context = init_run_context(
+        cli,
+        &fork_provider,
+        &ChainHeadSource::polling(),
+        body_logger,
+        true,
+    )
+    .await?;
+
+    let raw_reward_config = std::fs::read_to_string(reward_config_file)?;
+    let reward_config: RewardConfig = serde_json::from_str(&raw_reward_config)?;
+    reward_config
+        .validate()
+        .map_err(|err| anyhow::anyhow!("invalid reward config: {err}"))?;
+
+    // The ephemeral signer can't be in the signer list, so only the rest of the config is checked
+    let mut worker_config: WorkerConfig =
+        serde_json::from_slice(&std::fs::read(worker_config_file)?)?;
+    worker_config.signers.push(run_context.signer.address());
+    worker_config
+        .validate(&run_context.signer.address())
+        .map_err(|err| anyhow::anyhow!("invalid worker config: {err}"))?;
+
+    let cycle_config = CycleConfig {
+        worker_config,
+        reward_config,
+        reward_config_checksum: reward_config_checksum(&raw_reward_config),
+    };
+    let summary = run_once(&run_context, &cycle_config)
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("simulation skipped the period"))?;
+
+    println!(
+        "{}",
+        serde_json::to_string_pretty(&serde_json::json!({
+            "periodId": summary.period_id,
+            "entryCount": summary.entry_count,
+            "totalStakingReward": summary.total_staking_reward.to_string(),
+            "totalFeeReward": summary.total_fee_reward.to_string(),
+            "rewardConfigChecksum": format!("0x{}", hex::encode(cycle_config.reward_config_checksum)),
+        }))?
+    );
+
+    Ok(())
+}
+
+async fn replay_submission(run_context: &RunContext, submission_file: &Path) -> Result<()> {
+    let submission: Submission = serde_json::from_slice(&std::fs::read(submission_file)?)?;
+
+    let outcome = run_context
+        .worker_client
+        .stage(
+            &submission,
+            &run_context.signer.address(),
+            run_context.chain_id,
+            run_context.allow_empty_period,
+        )
+        .await?;
+    match outcome {
+        StageOutcome::Staged => info!("Staged submission for period {}", submission.period_id),
+        StageOutcome::AlreadyStaged => info!(
+            "Submission for period {} was already staged",
+            submission.period_id
+        ),
+    }
+
+    Ok(())
+}
+
+/// Stages the signed entries of `period_id` with the worker, along with the signer's own signature
+/// of each entry. Simulations never reach the worker.
+async fn submit_signed_entries(
+    run_context: &RunContext,
+    period_id: u32,
+    signed_reward_entries: &[SignedRewardEntry],
+    composition: RewardComposition,
+    attestation: Option<PeriodAttestation>,
+) -> Result<()> {
+    let signer_address = run_context.signer.address();
+    let entries = signed_reward_entries
+        .iter()
+        .map(|entry| {
+            let signature = entry
+                .signatures
+                .iter()
+                .find(|signature| signature.signer == signer_address)
+                .ok_or_else(|| {
+                    anyhow::anyhow!(
+                        "entry for {} has no signature of {}",
+                        to_checksum(&entry.reward.recipient, None),
+                        to_checksum(&signer_address, None)
+                    )
+                })?;
+            Ok(SubmissionRewardEntry {
+                recipient: entry.reward.recipient,
+                staking_reward: entry.reward.staking_reward,
+                fee_reward: entry.reward.fee_reward,
+                signature: signature.signature.clone(),
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let submission = Submission {
+        period_id,
+        chain_id: run_context.chain_id,
+        signer: signer_address,
+        entries,
+        composition,
+        attestation,
+    };
+
+    if run_context.simulation {
+        info!(
+            "Simulation: not staging submission of {} entries for period {}",
+            submission.entries.len(),
+            period_id
+        );
+        return Ok(());
+    }
+
+    let outcome = run_context
+        .worker_client
+        .stage(
+            &submission,
+            &signer_address,
+            run_context.chain_id,
+            run_context.allow_empty_period,
+        )
+        .await?;
+    match outcome {
+        StageOutcome::Staged => info!(
+            "Staged submission of {} entries for period {}",
+            submission.entries.len(),
+            period_id
+        ),
+        StageOutcome::AlreadyStaged => {
+            info!("Submission for period {} was already staged", period_id)
+        }
+    }
+
+    Ok(())
+}
+
+/// Signs entries read from `input_file`. JSON-lines input is read, signed and written one entry at
+/// a time so that files larger than memory can be processed.
+///
+/// With `continue_on_error`, a period that fails is skipped along with its remaining entries and
+/// reported at the end, so that a long backfill surfaces all problems in one run.
+async fn sign_input_file(
+    run_context: &RunContext,
+    input_file: &Path,
+    input_format: InputFormat,
+    output_file: &Path,
+    continue_on_error: bool,
+) -> Result<()> {
+    if run_context.signing_mode != SigningMode::PerEntry {
+        anyhow::bail!("signing input files is only supported in per-entry mode");
+    }
+
+    let mut reader = BufReader::new(std::fs::File::open(input_file)?);
+    let input_format = match input_format {
+        InputFormat::Auto => {
+            let first_char = loop {
+                let buffer = reader.fill_buf()?;
+                match buffer.iter().position(|byte| !byte.is_ascii_whitespace()) {
+                    Some(position) => break Some(buffer[position]),
+                    None if buffer.is_empty() => break None,
+                    None => {
+                        let len = buffer.len();
+                        reader.consume(len);
+                    }
+                }
+            };
+            if first_char == Some(b'[') {
+                InputFormat::Json
+            } else {
+                InputFormat::JsonLines
+            }
+        }
+        input_format => input_format,
+    };
+
+    let mut writer = BufWriter::new(std::fs::File::create(output_file)?);
+    let signers = run_context.signers();
+    let sign = |entries| {
+        sign_rewards(
+            entries,
+            &signers,
+            run_context.signature_scheme,
+            run_context.chain_id,
+            &run_context.reward_domains,
+            &run_context.resumed_entries,
+            run_context.sign_retry,
+        )
+    };
+
+    let mut entry_count = 0;
+    let mut succeeded_periods = BTreeSet::new();
+    let mut failed_periods = BTreeMap::new();
+    let record_failure = |failed_periods: &mut BTreeMap<u32, String>,
+                          period_id: u32,
+                          err: anyhow::Error|
+     -> Result<()> {
+        if !continue_on_error {
+            return Err(err);
+        }
+        warn!("Skipping period {}: {}", period_id, err);
+        failed_periods.insert(period_id, err.to_string());
+        Ok(())
+    };
+
+    match input_format {
+        InputFormat::Json => {
+            let reward_entries: Vec<RewardEntry> = serde_json::from_reader(reader)?;
+
+            // Periods are signed separately so that one bad period doesn't void the others
+            let mut periods: Vec<(u32, Vec<RewardEntry>)> = vec![];
+            for entry in reward_entries.into_iter() {
+                match periods
+                    .iter_mut()
+                    .find(|(period_id, _)| *period_id == entry.period_id)
+                {
+                    Some((_, entries)) => entries.push(entry),
+                    None => periods.push((entry.period_id, vec![entry])),
+                }
+            }
+
+            let mut signed_entries = vec![];
+            for (period_id, entries) in periods.into_iter() {
+                match sign(entries).await {
+                    Ok(mut signed) => {
+                        signed_entries.append(&mut signed);
+                        succeeded_periods.insert(period_id);
+                    }
+                    Err(err) => record_failure(&mut failed_periods, period_id, err)?,
+                }
+            }
+            entry_count = signed_entries.len();
+            writeln!(writer, "{}", to_canonical_json(&signed_entries)?)?;
+        }
+        InputFormat::JsonLines => {
+            for (ind_line, line) in reader.lines().enumerate() {
+                let line = line?;
+                if line.trim().is_empty() {
+                    continue;
+                }
+
+                let entry: RewardEntry = serde_json::from_str(&line)
+                    .map_err(|err| anyhow::anyhow!("line {}: {}", ind_line + 1, err))?;
+                let period_id = entry.period_id;
+                if failed_periods.contains_key(&period_id) {
+                    continue;
+                }
+
+                match sign(vec![entry]).await {
+                    Ok(signed) => {
+                        for signed_entry in signed {
+                            writeln!(writer, "{}", to_canonical_json(&signed_entry)?)?;
+                            entry_count += 1;
+                        }
+                        succeeded_periods.insert(period_id);
+                    }
+                    Err(err) => record_failure(
+                        &mut failed_periods,
+                        period_id,
+                        anyhow::anyhow!("line {}: {}", ind_line + 1, err),
+                    )?,
+                }
+            }
+        }
+        InputFormat::Auto => unreachable!(),
+    }
+    writer.flush()?;
+
+    info!(
+        "Signed {} entries into {}",
+        entry_count,
+        output_file.display()
     );
 
-    let run_context = RunContext {
-        chain_id,
-        signer,
-        eip_712_contract_name: cli.eip_712_contract_name,
-        reward_system_address: cli.reward_system_address,
+    if continue_on_error {
+        // A period that failed part way isn't complete even if some of its entries were signed
+        let succeeded_periods = succeeded_periods
+            .into_iter()
+            .filter(|period_id| !failed_periods.contains_key(period_id))
+            .collect::<Vec<_>>();
+        info!("Succeeded periods: {:?}", succeeded_periods);
+        if !failed_periods.is_empty() {
+            for (period_id, err) in failed_periods.iter() {
+                error!("Period {} failed: {}", period_id, err);
+            }
+            anyhow::bail!("{} periods failed to sign", failed_periods.len());
+        }
+    }
+
+    Ok(())
+}
+
+impl RunContext {
+    fn signers(&self) -> Vec<&Wallet> {
+        std::iter::once(&self.signer)
+            .chain(self.secondary_signer.as_ref())
+            .collect()
+    }
+}
+
+impl ConfigCache {
+    fn new(ttl: Duration) -> Self {
+        Self { ttl, entry: None }
+    }
+
+    async fn get(&mut self, run_context: &RunContext) -> Result<&CycleConfig> {
+        let expired = match &self.entry {
+            Some((_, fetched_at)) => fetched_at.elapsed() >= self.ttl,
+            None => true,
+        };
+
+        if expired {
+            match fetch_cycle_config(run_context).await {
+                Ok(cycle_config) => self.entry = Some((cycle_config, Instant::now())),
+                Err(err) => match &self.entry {
+                    Some((_, fetched_at)) => warn!(
+                        "Unable to fetch config, using config fetched {} s ago: {}",
+                        fetched_at.elapsed().as_secs(),
+                        err
+                    ),
+                    None => return Err(err),
+                },
+            }
+        }
+
+        Ok(&self.entry.as_ref().expect("config cached").0)
+    }
+}
+
+async fn fetch_cycle_config(run_context: &RunContext) -> Result<CycleConfig> {
+    let worker_config = run_context
+        .worker_client
+        .get_worker_config()
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("worker config not set"))?;
+    worker_config
+        .validate(&run_context.signer.address())
+        .map_err(|err| anyhow::anyhow!("invalid worker config: {err}"))?;
+
+    let reward_config_checksum = run_context.reward_config_checksum.ok_or_else(|| {
+        anyhow::anyhow!("reward config checksum must be set to fetch the reward config")
+    })?;
+    let reward_config = run_context
+        .worker_client
+        .get_reward_config_checked(&reward_config_checksum)
+        .await?;
+    reward_config
+        .validate()
+        .map_err(|err| anyhow::anyhow!("invalid reward config: {err}"))?;
+
+    Ok(CycleConfig {
+        worker_config,
+        reward_config,
+        reward_config_checksum,
+    })
+}
+
+/// Signs the last finished period. Returns `None` without signing if the worker is already past the
+/// period or has our submission for it, so that repeated cycles don't redo finished work.
+async fn run_once(
+    run_context: &RunContext,
+    cycle_config: &CycleConfig,
+) -> Result<Option<CycleSummary>> {
+    for signer in run_context.signers() {
+        let resolved_signer = signer.resolve_address().await?;
+        if resolved_signer != signer.address() {
+            anyhow::bail!(
+                "signer address changed from {} to {} since startup",
+                to_checksum(&signer.address(), None),
+                to_checksum(&resolved_signer, None)
+            );
+        }
+    }
+
+    // Only a finished period has its full debt and fee history
+    let worker_config = &cycle_config.worker_config;
+    let period_id = worker_config
+        .current_period_id(SystemTime::now())?
+        .checked_sub(1)
+        .ok_or_else(|| anyhow::anyhow!("no period has finished yet"))?;
+    let period_window = worker_config.period_window(period_id)?;
+
+    if !run_context.simulation {
+        let last_period_id = run_context.worker_client.get_last_period_id().await?;
+        if period_id < last_period_id {
+            info!(
+                "Skipping period {} as the worker already processed period {}",
+                period_id, last_period_id
+            );
+            return Ok(None);
+        }
+
+        let signer_staged = run_context
+            .worker_client
+            .get_signer_staged(period_id, &run_context.signer.address())
+            .await?;
+        if signer_staged {
+            info!("Skipping period {} as it's already staged", period_id);
+            return Ok(None);
+        }
+    }
+
+    // Fetched once so that every check within the cycle sees the same threshold
+    match fetch_signature_threshold(run_context, period_id).await {
+        Some(signature_threshold) => info!(
+            "Signature threshold for period {}: {}",
+            period_id, signature_threshold
+        ),
+        None => debug!("Signature threshold for period {} unknown", period_id),
+    }
+
+    if let Some(emission_tracker) = &run_context.emission_tracker {
+        audit_emission(
+            emission_tracker,
+            &cycle_config.reward_config,
+            period_id,
+            run_context.emission_audit_tolerance_bps,
+            run_context.display_decimals,
+        )
+        .await?;
+    }
+
+    let anchor_block = select_anchor_block(
+        &run_context.chain_head,
+        run_context.reward_system.client().as_ref(),
+        run_context.anchor_confirmations,
+    )
+    .await?;
+    let graphql_client = &run_context.graphql_client;
+    graphql_client.set_anchor_block(anchor_block);
+    debug!(
+        "Querying the subgraph at block {}",
+        graphql_client.anchor_block()
+    );
+    graphql_client.ensure_synced().await?;
+
+    // Weights depend on each staker's debt from before the period, so only the end is bounded
+    let debt_entries = graphql_client
+        .get_debt_entries(None, Some(period_window.1))
+        .await?;
+
+    let fees_accumulated = fetch_fees_accumulated(graphql_client, period_window).await?;
+
+    // Whatever wasn't claimed of the previous period is paid out again in this one
+    let rollover = {
+        let mut claim_ledger = run_context.claim_ledger.lock().await;
+        let new_claims = claim_ledger.update(graphql_client).await?;
+        debug!("Added {} reward claims to the claim ledger", new_claims);
+        if let Some(claims_file) = &run_context.claims_file {
+            claim_ledger.save(claims_file)?;
+        }
+
+        match period_id.checked_sub(1) {
+            Some(previous_period_id) => {
+                let distributed = distributed_totals(
+                    cycle_config,
+                    graphql_client,
+                    &mut claim_ledger,
+                    previous_period_id,
+                )
+                .await?;
+                compute_rollover(
+                    previous_period_id,
+                    distributed,
+                    claim_ledger.claimed_for_period(previous_period_id),
+                )?
+            }
+            None => Rollover::default(),
+        }
+    };
+    info!(
+        "Rollover into period {}: staking {}, fee {}",
+        period_id,
+        format_units_for_log(rollover.staking_reward, run_context.display_decimals),
+        format_units_for_log(rollover.fee_reward, run_context.display_decimals)
+    );
+
+    let mut composition = RewardComposition {
+        scheduled_staking_rewards: cycle_config
+            .reward_config
+            .scheduled_staking_rewards(period_id),
+        rollover_staking_rewards: rollover.staking_reward,
+        fees_accumulated,
+        rollover_fees: rollover.fee_reward,
+        ..Default::default()
+    };
+
+    let static_exclude_list = cycle_config
+        .reward_config
+        .exclude_list
+        .iter()
+        .cloned()
+        .collect::<HashSet<_>>();
+
+    let weights = compute_weights(run_context, &composition, &debt_entries, period_window)?;
+
+    let mut delegations = cycle_config.reward_config.delegations.clone();
+    delegations.extend(run_context.delegations.iter());
+
+    // Both delegators and delegates are checked so that delegating to a blocked address is dropped
+    let blocked_addresses = match &run_context.blocklist {
+        Some(blocklist) => {
+            let candidates = weights
+                .keys()
+                .cloned()
+                .chain(delegations.values().cloned())
+                .collect::<HashSet<_>>();
+            fetch_blocked_addresses(blocklist, candidates.into_iter()).await?
+        }
+        None => HashSet::new(),
+    };
+
+    debug!("Signing rewards generated...");
+    let weights = eligible_weights(
+        weights,
+        &delegations,
+        &static_exclude_list,
+        &blocked_addresses,
+    );
+    let (mut reward_entries, mut staking_allocation, mut fee_allocation) =
+        compute_rewards(run_context, period_id, &composition, &weights)?;
+
+    let (staking_adjustment, fee_adjustment) = apply_adjustments(
+        &mut reward_entries,
+        &run_context.adjustments,
+        run_context.chain_id,
+        period_id,
+    )?;
+    composition.staking_adjustment = staking_adjustment;
+    composition.fee_adjustment = fee_adjustment;
+    if !run_context.adjustments.is_empty() {
+        warn!(
+            "Net adjustments for period {}: staking {}, fee {}",
+            period_id,
+            format_signed_units_for_log(staking_adjustment, run_context.display_decimals),
+            format_signed_units_for_log(fee_adjustment, run_context.display_decimals)
+        );
+    }
+
+    if run_context.reward_schema == RewardSchema::V2 {
+        let nonces = fetch_reward_nonces(
+            &run_context.reward_system,
+            reward_entries.iter().map(|entry| entry.recipient),
+        )
+        .await?;
+        for entry in reward_entries.iter_mut() {
+            entry.nonce = Some(nonces[&entry.recipient]);
+        }
+    }
+
+    if let Some(allowlist) = &run_context.allowlist {
+        ensure_allowlisted(&reward_entries, allowlist)?;
+    }
+
+    let (total_staking_reward, total_fee_reward) = reward_totals(&reward_entries);
+
+    // Adjustments change what is allocated, so the split's totals are replaced by the final ones
+    staking_allocation.allocated = total_staking_reward;
+    fee_allocation.allocated = total_fee_reward;
+    let reconciliation =
+        ReconciliationReport::new(period_id, &composition, staking_allocation, fee_allocation);
+    reconciliation.log(run_context.display_decimals);
+    if let Some(path) = &run_context.reconciliation_report_file {
+        reconciliation.write(path)?;
+    }
+    reconciliation.ensure_balanced()?;
+
+    let summary = CycleSummary {
+        period_id,
+        entry_count: reward_entries.len(),
+        total_staking_reward,
+        total_fee_reward,
+    };
+    info!(
+        "Period {} totals over {} entries: staking {}, fee {}",
+        period_id,
+        summary.entry_count,
+        format_units_for_log(total_staking_reward, run_context.display_decimals),
+        format_units_for_log(total_fee_reward, run_context.display_decimals)
+    );
+
+    if let Some(approval) = &run_context.approval {
+        approval.ensure_approved(&reward_entries)?;
+    }
+
+    let attestation = if run_context.sign_period_attestation {
+        let attestation = sign_period_attestation(
+            period_id,
+            &reward_entries,
+            &run_context.signer,
+            &run_context.reward_domains.for_period(period_id),
+            run_context.sign_retry,
+        )
+        .await?;
+        info!(
+            "Period attestation over entries hash 0x{}: {:?}",
+            encode(&attestation.entries_hash),
+            encode(&attestation.signature)
+        );
+        Some(attestation)
+    } else {
+        None
+    };
+
+    // Taken before signing as the per entry path hands the composition over to the worker
+    let distributed = PoolAmounts {
+        staking_reward: composition.staking_reward_for_period(),
+        fee_reward: composition.fee_reward_for_period(),
+    };
+
+    match run_context.signing_mode {
+        SigningMode::PerEntry => {
+            if let Some((resumed_period_id, _)) = run_context
+                .resumed_entries
+                .keys()
+                .find(|(resumed_period_id, _)| *resumed_period_id != period_id)
+            {
+                anyhow::bail!(
+                    "resumed entries are for period {} but period {} is being processed",
+                    resumed_period_id,
+                    period_id
+                );
+            }
+
+            let signed_reward_entries = sign_rewards(
+                reward_entries,
+                &run_context.signers(),
+                run_context.signature_scheme,
+                run_context.chain_id,
+                &run_context.reward_domains,
+                &run_context.resumed_entries,
+                run_context.sign_retry,
+            )
+            .await?;
+            for entry in &signed_reward_entries {
+                for signature in &entry.signatures {
+                    info!("Sign Entry: {:?}", encode(&signature.signature));
+                }
+            }
+            if let Some(output) = &run_context.output {
+                write_signed_entries(output, &signed_reward_entries)?;
+            }
+
+            if run_context.submit {
+                submit_signed_entries(
+                    run_context,
+                    period_id,
+                    &signed_reward_entries,
+                    composition,
+                    attestation,
+                )
+                .await?;
+            }
+
+            let claims = signed_reward_entries
+                .iter()
+                .map(|entry| ClaimRewardForCall {
+                    period_id: entry.reward.period_id.into(),
+                    recipient: entry.reward.recipient,
+                    staking_reward: entry.reward.staking_reward,
+                    fee_reward: entry.reward.fee_reward,
+                    signatures: entry
+                        .signatures
+                        .iter()
+                        .map(|signature| Bytes::from(signature.signature.clone()))
+                        .collect(),
+                })
+                .collect::<Vec<_>>();
+
+            if let Some(meta_tx) = &run_context.meta_tx {
+                let signed_requests = sign_forward_requests(
+                    meta_tx,
+                    &run_context.signer,
+                    run_context.reward_system.address(),
+                    &claims,
+                    run_context.sign_retry,
+                )
+                .await?;
+                std::fs::write(&meta_tx.output_file, to_canonical_json(&signed_requests)?)?;
+                info!(
+                    "Wrote {} signed forward requests to {}",
+                    signed_requests.len(),
+                    meta_tx.output_file.display()
+                );
+            }
+
+            if let Some(safe_batch_file) = &run_context.safe_batch_file {
+                SafeBatch::from_claims(
+                    run_context.chain_id,
+                    run_context.reward_system.address(),
+                    period_id,
+                    claims,
+                )?
+                .write(safe_batch_file)?;
+                info!("Wrote Safe batch to {}", safe_batch_file.display());
+            }
+        }
+        SigningMode::Batch => {
+            let signed_batch = sign_reward_batch(
+                reward_entries,
+                &run_context.signer,
+                run_context.chain_id,
+                &run_context.reward_domains.for_period(period_id),
+                run_context.sign_retry,
+            )
+            .await?;
+            info!(
+                "Sign Batch of {} entries: {:?}",
+                signed_batch.recipients.len(),
+                encode(&signed_batch.signature.signature)
+            );
+
+            if let Some(output) = &run_context.output {
+                write_output(
+                    output,
+                    &signed_batch,
+                    &format!("signed batch of {} entries", signed_batch.recipients.len()),
+                )?;
+            }
+        }
+    }
+    info!("Finished signing rewards");
+
+    // A simulated period was never distributed, so it mustn't feed the next period's rollover
+    if !run_context.simulation {
+        let mut claim_ledger = run_context.claim_ledger.lock().await;
+        claim_ledger.record_distribution(period_id, distributed);
+        if let Some(claims_file) = &run_context.claims_file {
+            claim_ledger.save(claims_file)?;
+        }
+    }
+
+    Ok(Some(summary))
+}
+
+/// Streams the exchange and perp fees charged within `window` and sums their pool share, so that
+/// busy periods don't hold every fee entry in memory.
+async fn fetch_fees_accumulated(
+    graphql_client: &GraphqlClient,
+    window: (SystemTime, SystemTime),
+) -> Result<U256> {
+    let exchange_fees = sum_pool_fees(
+        graphql_client.get_exchange_entries_stream(Some(window.0), Some(window.1)),
+        window,
+    )
+    .await?;
+    let perp_fees = sum_pool_fees(
+        graphql_client.get_perp_fee_entries_stream(Some(window.0), Some(window.1)),
+        window,
+    )
+    .await?;
+
+    Ok(exchange_fees.checked_add(perp_fees).expect("overflow"))
+}
+
+/// Totals distributed for `period_id` as recorded in the claim ledger. Periods signed before the
+/// ledger was kept are reconstructed from their scheduled rewards, fees and rollover, which misses
+/// any adjustment made to them, and recorded so that they're only fetched once.
+async fn distributed_totals(
+    cycle_config: &CycleConfig,
+    graphql_client: &GraphqlClient,
+    claim_ledger: &mut ClaimLedger,
+    period_id: u32,
+) -> Result<PoolAmounts> {
+    if let Some(distributed) = claim_ledger.distributed.get(&period_id) {
+        return Ok(*distributed);
+    }
+
+    // Continue from the latest recorded period before `period_id`, or from the very first period
+    let (mut next_period_id, mut rollover) =
+        match claim_ledger.distributed.range(..period_id).next_back() {
+            Some((&recorded_period_id, &distributed)) => (
+                recorded_period_id + 1,
+                compute_rollover(
+                    recorded_period_id,
+                    distributed,
+                    claim_ledger.claimed_for_period(recorded_period_id),
+                )?,
+            ),
+            None => (0, Rollover::default()),
+        };
+    loop {
+        warn!(
+            "Period {} is missing from the claim ledger, reconstructing its distributed totals",
+            next_period_id
+        );
+        let fees_accumulated = fetch_fees_accumulated(
+            graphql_client,
+            cycle_config.worker_config.period_window(next_period_id)?,
+        )
+        .await?;
+        let distributed = PoolAmounts {
+            staking_reward: cycle_config
+                .reward_config
+                .scheduled_staking_rewards(next_period_id)
+                .checked_add(rollover.staking_reward)
+                .expect("overflow"),
+            fee_reward: fees_accumulated
+                .checked_add(rollover.fee_reward)
+                .expect("overflow"),
+        };
+        claim_ledger.record_distribution(next_period_id, distributed);
+
+        if next_period_id == period_id {
+            return Ok(distributed);
+        }
+        rollover = compute_rollover(
+            next_period_id,
+            distributed,
+            claim_ledger.claimed_for_period(next_period_id),
+        )?;
+        next_period_id += 1;
+    }
+}
+
+/// Sums the pool share of all fees charged within `[from, to)`.
+async fn sum_pool_fees<T, S>(entries: S, window: (SystemTime, SystemTime)) -> Result<U256>
+where
+    T: PoolableFeeEntry,
+    S: Stream<Item = Result<T>>,
+{
+    let (from, to) = window;
+    entries
+        .try_fold(U256::zero(), |acc, entry| async move {
+            if entry.timestamp() >= from && entry.timestamp() < to {
+                Ok(acc.checked_add(entry.fee_for_pool()).expect("overflow"))
+            } else {
+                Ok(acc)
+            }
+        })
+        .await
+}
+
+/// Weights of all stakers by their debt within `window`, checked for plausibility.
+fn compute_weights(
+    run_context: &RunContext,
+    composition: &RewardComposition,
+    debt_entries: &[DebtEntry],
+    window: (SystemTime, SystemTime),
+) -> Result<HashMap<Address, U256>> {
+    let weights = run_context.weight_strategy.weights(debt_entries, window);
+    validate_weights(
+        debt_entries,
+        &weights,
+        composition
+            .staking_reward_for_period()
+            .checked_add(composition.fee_reward_for_period())
+            .expect("overflow"),
+    )?;
+    if let Some(tolerance_bps) = run_context.debt_conservation_tolerance_bps {
+        check_debt_conservation(debt_entries, &weights, window, tolerance_bps)?;
+    }
+
+    Ok(weights)
+}
+
+/// Leaves out stakers in `exclude_list` or `blocked_addresses`, so that their share goes to the
+/// remaining stakers instead of being lost, and moves the weights of the rest to their delegates.
+/// The result is in address order, ready to be split.
+fn eligible_weights(
+    mut weights: HashMap<Address, U256>,
+    delegations: &HashMap<Address, Address>,
+    exclude_list: &HashSet<Address>,
+    blocked_addresses: &HashSet<Address>,
+) -> Vec<(Address, U256)> {
+    let staker_count = weights.len();
+    weights.retain(|address, _| !exclude_list.contains(address));
+    info!(
+        "Excluded {} stakers by config",
+        staker_count - weights.len()
+    );
+    let staker_count = weights.len();
+    weights.retain(|address, _| !blocked_addresses.contains(address));
+    info!(
+        "Excluded {} stakers by on-chain blocklist",
+        staker_count - weights.len()
+    );
+
+    let excluded_delegates = exclude_list
+        .union(blocked_addresses)
+        .cloned()
+        .collect::<HashSet<_>>();
+
+    into_sorted_weights(apply_delegations(weights, delegations, &excluded_delegates))
+}
+
+/// Splits the staking reward and the fees of `period_id` among stakers by `weights`. Recipients are
+/// in address order with dust on the last one, so every signer ends up with the same entries.
+fn compute_rewards(
+    run_context: &RunContext,
+    period_id: u32,
+    composition: &RewardComposition,
+    weights: &[(Address, U256)],
+) -> Result<(Vec<RewardEntry>, PoolAllocation, PoolAllocation)> {
+    let staking_pool = composition.staking_reward_for_period();
+    let fee_pool = composition.fee_reward_for_period();
+
+    let staking = split_pool(staking_pool, weights, run_context.audit_math)?;
+    let fees = split_pool(fee_pool, weights, run_context.audit_math)?;
+    for (pool_name, undistributed) in [
+        ("Staking reward", staking.undistributed),
+        ("Fee reward", fees.undistributed),
+    ] {
+        if !undistributed.is_zero() {
+            warn!(
+                "{} of {} for period {} has no recipient",
+                pool_name,
+                format_units_for_log(undistributed, run_context.display_decimals),
+                period_id
+            );
+        }
+    }
+
+    let staking_allocation = PoolAllocation {
+        allocated: staking_pool - staking.undistributed,
+        dust: staking.dust,
+        undistributed: staking.undistributed,
+    };
+    let fee_allocation = PoolAllocation {
+        allocated: fee_pool - fees.undistributed,
+        dust: fees.dust,
+        undistributed: fees.undistributed,
+    };
+
+    let reward_entries = staking
+        .shares
+        .into_iter()
+        .zip(fees.shares)
+        .filter(|((_, staking_reward), (_, fee_reward))| {
+            !staking_reward.is_zero() || !fee_reward.is_zero()
+        })
+        .map(
+            |((recipient, staking_reward), (_, fee_reward))| RewardEntry {
+                chain_id: run_context.chain_id,
+                period_id,
+                recipient,
+                staking_reward,
+                fee_reward,
+                nonce: None,
+            },
+        )
+        .collect();
+
+    Ok((reward_entries, staking_allocation, fee_allocation))
+}
+
+/// Asks the worker for the signature threshold of `period_id`, falling back to the configured one
+/// for workers that don't expose it.
+async fn fetch_signature_threshold(run_context: &RunContext, period_id: u32) -> Option<u32> {
+    if run_context.simulation {
+        return run_context.signature_threshold;
+    }
+
+    match run_context
+        .worker_client
+        .get_signature_threshold(period_id)
+        .await
+    {
+        Ok(signature_threshold) => Some(signature_threshold),
+        Err(err) => {
+            debug!("Unable to fetch signature threshold from worker: {err}");
+            run_context.signature_threshold
+        }
+    }
+}
+
+const FORWARD_REQUEST_TYPE: &str =
+    "ForwardRequest(address from,address to,uint256 value,uint256 gas,uint256 nonce,bytes data)";
+const PERIOD_ATTESTATION_TYPE: &str =
+    "PeriodAttestation(uint256 periodId,bytes32 entriesHash,uint256 totalStakingReward,uint256 totalFeeReward)";
+
+// Hard-coded params
+const CONTRACT_QUERY_CONCURRENCY: usize = 20;
+const STARTUP_RETRY_BASE_SECS: u64 = 1;
+const STARTUP_RETRY_MAX_SECS: u64 = 60;
+
+async fn fetch_reward_signers<M>(reward_system: &LnRewardSystem<M>) -> Result<Vec<Address>>
+where
+    M: Middleware + 'static,
+{
+    let signer_count = reward_system.get_signer_count().call().await?;
+
+    let mut reward_signers = vec![];
+    for ind in 0..signer_count.as_u64() {
+        reward_signers.push(reward_system.reward_signers(ind.into()).call().await?);
+    }
+
+    Ok(reward_signers)
+}
+
+/// Reads the current reward nonce of each recipient. Recipients that never claimed read as zero
+/// from the contract mapping.
+async fn fetch_reward_nonces<M, I>(
+    reward_system: &LnRewardSystem<M>,
+    recipients: I,
+) -> Result<HashMap<Address, U256>>
+where
+    M: Middleware + 'static,
+    I: Iterator<Item = Address>,
+{
+    futures::stream::iter(recipients.map(|recipient| async move {
+        let nonce = reward_system
+            .nonces(recipient)
+            .call()
+            .await
+            .map_err(|err| {
+                anyhow::anyhow!(
+                    "unable to fetch nonce of {}: {}",
+                    to_checksum(&recipient, None),
+                    err
+                )
+            })?;
+        Ok((recipient, nonce))
+    }))
+    .buffered(CONTRACT_QUERY_CONCURRENCY)
+    .collect::<Vec<Result<_>>>()
+    .await
+    .into_iter()
+    .collect()
+}
+
+/// Warns when the scheduled staking rewards up to `period_id` diverge from what the contract
+/// reports as distributed by more than `tolerance_bps`.
+async fn audit_emission<M>(
+    emission_tracker: &EmissionTracker<M>,
+    reward_config: &RewardConfig,
+    period_id: u32,
+    tolerance_bps: u64,
+    display_decimals: u32,
+) -> Result<()>
+where
+    M: Middleware + 'static,
+{
+    let scheduled = reward_config.scheduled_staking_total(period_id);
+    let distributed = emission_tracker.total_distributed().call().await?;
+
+    let difference = if scheduled > distributed {
+        scheduled - distributed
+    } else {
+        distributed - scheduled
     };
+    let tolerance = scheduled * U256::from(tolerance_bps) / U256::from(10_000u64);
 
-    loop {
-        if let Err(err) = run_once(&run_context).await {
-            error!("Error: {err}");
+    if difference > tolerance {
+        warn!(
+            "Scheduled staking rewards up to period {} ({}) diverge from distributed total ({})",
+            period_id,
+            format_units_for_log(scheduled, display_decimals),
+            format_units_for_log(distributed, display_decimals)
+        );
+    } else {
+        debug!(
+            "Scheduled staking rewards up to period {}: {}; distributed: {}",
+            period_id,
+            format_units_for_log(scheduled, display_decimals),
+            format_units_for_log(distributed, display_decimals)
+        );
+    }
+
+    Ok(())
+}
+
+/// Returns the subset of `addresses` flagged by the blocklist contract. Each address is queried
+/// once per call.
+async fn fetch_blocked_addresses<M, I>(
+    blocklist: &Blocklist<M>,
+    addresses: I,
+) -> Result<HashSet<Address>>
+where
+    M: Middleware + 'static,
+    I: Iterator<Item = Address>,
+{
+    let results = futures::stream::iter(addresses.map(|address| async move {
+        let blocked = blocklist.is_blocked(address).call().await.map_err(|err| {
+            anyhow::anyhow!(
+                "unable to query blocklist for {}: {}",
+                to_checksum(&address, None),
+                err
+            )
+        })?;
+        Ok((address, blocked))
+    }))
+    .buffer_unordered(CONTRACT_QUERY_CONCURRENCY)
+    .collect::<Vec<Result<_>>>()
+    .await;
+
+    let mut blocked_addresses = HashSet::new();
+    for result in results.into_iter() {
+        let (address, blocked) = result?;
+        if blocked {
+            blocked_addresses.insert(address);
+        }
+    }
+
+    Ok(blocked_addresses)
+}
+
+/// Signs every entry with each of `signers`, with the signatures sorted by signer address.
+async fn sign_rewards(
+    mut reward_entries: Vec<RewardEntry>,
+    signers: &[&Wallet],
+    signature_scheme: SignatureScheme,
+    chain_id: u64,
+    domains: &RewardDomains,
+    resumed_entries: &HashMap<(u32, Address), SignedRewardEntry>,
+    retry: SignRetry,
+) -> Result<Vec<SignedRewardEntry>> {
+    ensure_chain_id(&reward_entries, chain_id)?;
+
+    // Independent signers have to produce their output in the same order to be aggregated
+    reward_entries.sort();
+
+    let mut signed_entries = vec![];
+
+    let mut resumed_count = 0;
+
+    for entry in reward_entries.into_iter() {
+        if let Some(resumed_entry) = resumed_entries.get(&(entry.period_id, entry.recipient)) {
+            if resumed_entry.reward != entry {
+                anyhow::bail!(
+                    "resumed entry for {} does not match the computed reward",
+                    to_checksum(&entry.recipient, None)
+                );
+            }
+            if signers.iter().all(|signer| {
+                resumed_entry
+                    .signatures
+                    .iter()
+                    .any(|signature| signature.signer == signer.address())
+            }) {
+                signed_entries.push(resumed_entry.clone());
+                resumed_count += 1;
+                continue;
+            }
+        }
+
+        let mut signatures = vec![];
+        for signer in signers.iter() {
+            let (signature, recovered) = match signature_scheme {
+                SignatureScheme::Eip712 => {
+                    let domain = domains.for_period(entry.period_id);
+                    let payload = Eip712RewardEntry {
+                        inner: &entry,
+                        domain: &domain,
+                    };
+                    let signature =
+                        sign_with_retry(retry, || signer.sign_typed_data(&payload)).await?;
+                    let recovered = Wallet::recover_reward_signer(&entry, &domain, &signature)?;
+                    (signature, recovered)
+                }
+                SignatureScheme::Eip191 => {
+                    let message = reward_packed_hash(&entry)?;
+                    let signature = to_electrum_v(
+                        sign_with_retry(retry, || signer.sign_message(message)).await?,
+                    )?;
+                    let recovered = signature.recover(hash_message(message))?;
+                    (signature, recovered)
+                }
+            };
+
+            ensure_recovered_signer(
+                recovered,
+                signer.address(),
+                &format!("entry of {}", to_checksum(&entry.recipient, None)),
+            )?;
+
+            signatures.push(Signature {
+                signer: signer.address(),
+                signature: signature.to_vec(),
+            });
         }
+        signatures.sort_by_key(|signature| signature.signer);
+
+        signed_entries.push(SignedRewardEntry {
+            reward: entry,
+            signatures,
+        })
+    }
 
-        std::thread::sleep(Duration::from_millis(cli.process_interval));
+    if resumed_count > 0 {
+        debug!("Reused {} already signed entries", resumed_count);
     }
+
+    Ok(signed_entries)
 }
 
-async fn run_once(run_context: &RunContext) -> Result<()> {
-    debug!("Signing rewards generated...");
-    let mut reward_entries = vec![];
-    let recipient_hex = "0x5C9d6aFE82C8f1c33aB274C577932F2D40778347";
-    let recipient = Address::from_slice(&hex::decode(&recipient_hex[2..]).unwrap());
-    reward_entries.push(RewardEntry {
-        chain_id: run_context.chain_id,
-        period_id: 136,
-        recipient: recipient,
-        staking_reward: U256::from_dec_str("10000000000000000000000").unwrap(),
-        fee_reward: U256::from_dec_str("1000000000000000").unwrap(),
-    });
-
-    let signed_reward_entries = sign_rewards(
-        reward_entries,
-        &run_context.signer,
-        run_context.chain_id,
-        &run_context.eip_712_contract_name,
-        run_context.reward_system_address,
-    )
-    .await?;
-    for entry in &signed_reward_entries {
-        info!("Sign Entry: {:?}", encode(&entry.signatures[0].signature));
+/// Writes signed entries as a canonical JSON array, which [load_signed_entries] reads back. A path
+/// of `-` writes to stdout instead.
+fn write_signed_entries(path: &Path, entries: &[SignedRewardEntry]) -> Result<()> {
+    write_output(path, &entries, &format!("{} signed entries", entries.len()))
+}
+
+/// Writes `value` as canonical JSON to `path`, or to stdout for `-`.
+fn write_output<T>(path: &Path, value: &T, description: &str) -> Result<()>
+where
+    T: Serialize,
+{
+    let json = to_canonical_json(value)?;
+
+    if path == Path::new("-") {
+        println!("{}", json);
+    } else {
+        std::fs::write(path, json)?;
+        info!("Wrote {} to {}", description, path.display());
     }
-    info!("Finished signing rewards");
 
     Ok(())
 }
 
-async fn sign_rewards(
-    reward_entries: Vec<RewardEntry>,
+/// Reads signed entries written by a previous run, either as a JSON array or as JSON lines.
+fn load_signed_entries(path: &Path) -> Result<Vec<SignedRewardEntry>> {
+    let content = std::fs::read_to_string(path)?;
+
+    if content.trim_start().starts_with('[') {
+        return Ok(serde_json::from_str(&content)?);
+    }
+
+    content
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(ind_line, line)| {
+            serde_json::from_str(line)
+                .map_err(|err| anyhow::anyhow!("line {}: {}", ind_line + 1, err))
+        })
+        .collect()
+}
+
+/// Produces a single signature over all entries of the period, encoded as a `Reward[]` array
+/// member of a `RewardBatch` struct.
+async fn sign_reward_batch(
+    mut reward_entries: Vec<RewardEntry>,
     signer: &Wallet,
     chain_id: u64,
-    contract_name: &str,
-    contract_address: Address,
-) -> Result<Vec<SignedRewardEntry>> {
-    struct Eip712RewardEntry<'a> {
-        inner: &'a RewardEntry,
-        chain_id: u64,
-        contract_name: &'a str,
-        contract_address: Address,
+    domain: &EIP712Domain,
+    retry: SignRetry,
+) -> Result<SignedRewardBatch> {
+    ensure_chain_id(&reward_entries, chain_id)?;
+
+    if reward_entries
+        .iter()
+        .any(|entry| entry.nonce.is_some() != reward_entries[0].nonce.is_some())
+    {
+        anyhow::bail!("batch mixes entries with and without nonce");
+    }
+
+    // The batch hash covers the entry order, so it must not depend on how entries were computed
+    reward_entries.sort();
+
+    let payload = Eip712RewardBatch {
+        entries: &reward_entries,
+        domain,
+    };
+    let signature = sign_with_retry(retry, || signer.sign_typed_data(&payload)).await?;
+    ensure_recovered_signer(
+        signature.recover(payload.encode_eip712()?)?,
+        signer.address(),
+        "batch",
+    )?;
+
+    Ok(SignedRewardBatch {
+        recipients: reward_entries.iter().map(|entry| entry.recipient).collect(),
+        staking_rewards: reward_entries
+            .iter()
+            .map(|entry| entry.staking_reward)
+            .collect(),
+        fee_rewards: reward_entries
+            .iter()
+            .map(|entry| entry.fee_reward)
+            .collect(),
+        nonces: reward_entries
+            .iter()
+            .filter_map(|entry| entry.nonce)
+            .collect(),
+        signature: Signature {
+            signer: signer.address(),
+            signature: signature.to_vec(),
+        },
+    })
+}
+
+/// Wraps each claim in a forwarder request from the reward signer and signs it with the forwarder
+/// domain. Nonces are consecutive from the signer's current forwarder nonce, matching the order in
+/// which the relayer has to submit them.
+async fn sign_forward_requests(
+    meta_tx: &MetaTxConfig,
+    signer: &Wallet,
+    reward_system: Address,
+    claims: &[ClaimRewardForCall],
+    retry: SignRetry,
+) -> Result<Vec<SignedForwardRequest>> {
+    let first_nonce = meta_tx.forwarder.get_nonce(signer.address()).call().await?;
+
+    let mut signed_requests = vec![];
+    for (ind, claim) in claims.iter().enumerate() {
+        let request = ForwardRequest {
+            from: signer.address(),
+            to: reward_system,
+            value: U256::zero(),
+            gas: meta_tx.gas,
+            nonce: first_nonce + U256::from(ind),
+            data: claim.clone().encode(),
+        };
+        let payload = Eip712ForwardRequest {
+            inner: &request,
+            domain: &meta_tx.domain,
+        };
+        let signature = sign_with_retry(retry, || signer.sign_typed_data(&payload)).await?;
+        ensure_recovered_signer(
+            signature.recover(payload.encode_eip712()?)?,
+            signer.address(),
+            &format!("forward request {}", request.nonce),
+        )?;
+
+        signed_requests.push(SignedForwardRequest {
+            request,
+            signature: signature.to_vec(),
+        });
+    }
+
+    Ok(signed_requests)
+}
+
+/// Signs the period id, a hash over all entries and the reward totals with the reward domain.
+async fn sign_period_attestation(
+    period_id: u32,
+    reward_entries: &[RewardEntry],
+    signer: &Wallet,
+    domain: &EIP712Domain,
+    retry: SignRetry,
+) -> Result<PeriodAttestation> {
+    struct Eip712PeriodAttestation<'a> {
+        period_id: u32,
+        entries_hash: [u8; 32],
+        total_staking_reward: U256,
+        total_fee_reward: U256,
+        domain: &'a EIP712Domain,
     }
 
-    impl<'a> Eip712 for Eip712RewardEntry<'a> {
+    impl<'a> Eip712 for Eip712PeriodAttestation<'a> {
         type Error = std::convert::Infallible;
 
         fn domain(&self) -> std::result::Result<EIP712Domain, Self::Error> {
-            Ok(EIP712Domain {
-                name: Some(self.contract_name.to_owned()),
-                version: Some("1".into()),
-                chain_id: Some(self.chain_id.into()),
-                verifying_contract: Some(self.contract_address),
-                salt: None,
-            })
+            Ok(self.domain.clone())
         }
 
         fn type_hash() -> std::result::Result<[u8; 32], Self::Error> {
-            Ok(keccak256(
-                "Reward(uint256 periodId,address recipient,uint256 stakingReward,uint256 feeReward)",
-            ))
+            Ok(keccak256(PERIOD_ATTESTATION_TYPE))
         }
 
         fn struct_hash(&self) -> std::result::Result<[u8; 32], Self::Error> {
             Ok(keccak256(abi::encode(&[
                 Token::Uint(U256::from(Self::type_hash()?)),
-                Token::Uint(self.inner.period_id.into()),
-                Token::Address(self.inner.recipient),
-                Token::Uint(self.inner.staking_reward),
-                Token::Uint(self.inner.fee_reward),
+                Token::Uint(self.period_id.into()),
+                Token::FixedBytes(self.entries_hash.to_vec()),
+                Token::Uint(self.total_staking_reward),
+                Token::Uint(self.total_fee_reward),
             ])))
         }
     }
 
-    let mut signed_entries = vec![];
+    // Same encoding as the `Reward[]` member of a batch
+    let entries_hash = keccak256(
+        reward_entries
+            .iter()
+            .flat_map(reward_struct_hash)
+            .collect::<Vec<_>>(),
+    );
 
-    for entry in reward_entries.into_iter() {
-        let mut failed_attempts = 0;
+    let (total_staking_reward, total_fee_reward) = reward_totals(reward_entries);
 
-        let signature = loop {
-            match signer
-                .sign_typed_data(&Eip712RewardEntry {
-                    inner: &entry,
-                    chain_id,
-                    contract_name,
-                    contract_address,
-                })
-                .await
-            {
-                Ok(value) => break value,
-                Err(err) => {
-                    failed_attempts += 1;
-                    if failed_attempts >= 10 {
-                        anyhow::bail!("Signing still fails after 10 attempts");
-                    } else {
-                        error!(
-                            "Failed to sign reward entry. Retrying (attempt {}) after 10 seconds: {}",
-                            failed_attempts + 1,
-                            err
-                        );
-                        tokio::time::sleep(Duration::from_secs(10)).await;
-                    }
+    let payload = Eip712PeriodAttestation {
+        period_id,
+        entries_hash,
+        total_staking_reward,
+        total_fee_reward,
+        domain,
+    };
+    let signature = sign_with_retry(retry, || signer.sign_typed_data(&payload)).await?;
+    ensure_recovered_signer(
+        signature.recover(payload.encode_eip712()?)?,
+        signer.address(),
+        &format!("attestation of period {}", period_id),
+    )?;
+
+    Ok(PeriodAttestation {
+        entries_hash: entries_hash.to_vec(),
+        total_staking_reward,
+        total_fee_reward,
+        signature: signature.to_vec(),
+    })
+}
+
+/// A signature that doesn't recover to the signer would only be noticed on-chain, so every signing
+/// path checks the recovered address before using the signature.
+fn ensure_recovered_signer(recovered: Address, signer: Address, signed: &str) -> Result<()> {
+    if recovered != signer {
+        anyhow::bail!(
+            "signature for {} recovers to {} instead of signer {}",
+            signed,
+            to_checksum(&recovered, None),
+            to_checksum(&signer, None)
+        );
+    }
+
+    Ok(())
+}
+
+/// Sums of staking and fee rewards over all entries.
+fn reward_totals(reward_entries: &[RewardEntry]) -> (U256, U256) {
+    let mut total_staking_reward = U256::zero();
+    let mut total_fee_reward = U256::zero();
+    for entry in reward_entries.iter() {
+        total_staking_reward = total_staking_reward
+            .checked_add(entry.staking_reward)
+            .expect("overflow");
+        total_fee_reward = total_fee_reward
+            .checked_add(entry.fee_reward)
+            .expect("overflow");
+    }
+
+    (total_staking_reward, total_fee_reward)
+}
+
+/// KMS signers return `sign_message` signatures with an EIP-155 `v`, which `ecrecover` based
+/// verifiers reject. Maps the raw, electrum and EIP-155 `v` encodings to 27 or 28.
+fn to_electrum_v(signature: ethers::types::Signature) -> Result<ethers::types::Signature> {
+    let v = match signature.v {
+        0 | 1 => signature.v + 27,
+        27 | 28 => signature.v,
+        v if v >= 35 => 27 + (v - 35) % 2,
+        v => anyhow::bail!("malformed signature v: {}", v),
+    };
+    Ok(ethers::types::Signature { v, ..signature })
+}
+
+fn ensure_chain_id(reward_entries: &[RewardEntry], chain_id: u64) -> Result<()> {
+    let mismatched_recipients = reward_entries
+        .iter()
+        .filter(|entry| entry.chain_id != chain_id)
+        .map(|entry| to_checksum(&entry.recipient, None))
+        .collect::<Vec<_>>();
+    if !mismatched_recipients.is_empty() {
+        anyhow::bail!(
+            "entries with chain id other than {}: {}",
+            chain_id,
+            mismatched_recipients.join(", ")
+        );
+    }
+
+    Ok(())
+}
+
+/// How often and how patiently a failed signature is retried.
+#[derive(Debug, Clone, Copy)]
+struct SignRetry {
+    max_attempts: u32,
+    delay: Duration,
+}
+
+async fn sign_with_retry<F, Fut>(retry: SignRetry, mut sign: F) -> Result<ethers::types::Signature>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = std::result::Result<ethers::types::Signature, WalletError>>,
+{
+    let mut failed_attempts = 0;
+
+    loop {
+        match sign().await {
+            Ok(value) => break Ok(value),
+            Err(err) => {
+                failed_attempts += 1;
+                if failed_attempts >= retry.max_attempts {
+                    anyhow::bail!("Signing still fails after {} attempts", failed_attempts);
+                } else {
+                    error!(
+                        "Failed to sign reward entry. Retrying (attempt {}) after {:?}: {}",
+                        failed_attempts + 1,
+                        retry.delay,
+                        err
+                    );
+                    tokio::time::sleep(retry.delay).await;
                 }
             }
+        }
+    }
+}
+
+/// Moves the weights of delegating stakers to their delegates, summing the weights of delegators
+/// that point at the same delegate. Delegations pointing at an excluded address are ignored.
+fn apply_delegations(
+    weights: HashMap<Address, U256>,
+    delegations: &HashMap<Address, Address>,
+    exclude_list: &HashSet<Address>,
+) -> HashMap<Address, U256> {
+    let mut merged_weights: HashMap<Address, U256> = HashMap::new();
+
+    for (owner, weight) in weights.into_iter() {
+        let recipient = match delegations.get(&owner) {
+            Some(delegate) if exclude_list.contains(delegate) => {
+                warn!(
+                    "Dropping delegation from {} to excluded address {}",
+                    to_checksum(&owner, None),
+                    to_checksum(delegate, None)
+                );
+                owner
+            }
+            Some(delegate) => {
+                info!(
+                    "Delegating rewards of {} to {}",
+                    to_checksum(&owner, None),
+                    to_checksum(delegate, None)
+                );
+                *delegate
+            }
+            None => owner,
         };
 
-        signed_entries.push(SignedRewardEntry {
-            reward: entry,
-            signatures: vec![Signature {
-                signer: signer.address(),
-                signature: signature.to_vec(),
-            }],
-        })
+        let merged = merged_weights.entry(recipient).or_default();
+        *merged = merged.checked_add(weight).expect("overflow");
     }
 
-    Ok(signed_entries)
+    merged_weights
+}
+
+/// Applies governance adjustments on top of the computed rewards and returns the net staking and fee
+/// deltas, which have to be reflected in the period composition.
+fn apply_adjustments(
+    reward_entries: &mut Vec<RewardEntry>,
+    adjustments: &[RewardAdjustment],
+    chain_id: u64,
+    period_id: u32,
+) -> Result<(I256, I256)> {
+    let mut staking_adjustment = I256::zero();
+    let mut fee_adjustment = I256::zero();
+
+    for adjustment in adjustments.iter() {
+        if adjustment.period_id != period_id {
+            anyhow::bail!(
+                "adjustments are for period {} but period {} is being processed",
+                adjustment.period_id,
+                period_id
+            );
+        }
+
+        let index = match reward_entries
+            .iter()
+            .position(|entry| entry.recipient == adjustment.recipient)
+        {
+            Some(index) => index,
+            None => {
+                reward_entries.push(RewardEntry {
+                    chain_id,
+                    period_id,
+                    recipient: adjustment.recipient,
+                    staking_reward: U256::zero(),
+                    fee_reward: U256::zero(),
+                    nonce: None,
+                });
+                reward_entries.len() - 1
+            }
+        };
+        let entry = &mut reward_entries[index];
+
+        let staking_reward =
+            apply_signed_delta(entry.staking_reward, adjustment.staking_reward_delta).ok_or_else(
+                || {
+                    anyhow::anyhow!(
+                        "staking reward adjustment for {} goes below zero",
+                        to_checksum(&adjustment.recipient, None)
+                    )
+                },
+            )?;
+        let fee_reward = apply_signed_delta(entry.fee_reward, adjustment.fee_reward_delta)
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "fee reward adjustment for {} goes below zero",
+                    to_checksum(&adjustment.recipient, None)
+                )
+            })?;
+
+        warn!(
+            "Adjusted rewards of {} for period {}: staking {} -> {}, fee {} -> {}",
+            to_checksum(&adjustment.recipient, None),
+            period_id,
+            entry.staking_reward,
+            staking_reward,
+            entry.fee_reward,
+            fee_reward
+        );
+
+        entry.staking_reward = staking_reward;
+        entry.fee_reward = fee_reward;
+
+        staking_adjustment = staking_adjustment
+            .checked_add(adjustment.staking_reward_delta)
+            .ok_or_else(|| anyhow::anyhow!("overflow"))?;
+        fee_adjustment = fee_adjustment
+            .checked_add(adjustment.fee_reward_delta)
+            .ok_or_else(|| anyhow::anyhow!("overflow"))?;
+    }
+
+    Ok((staking_adjustment, fee_adjustment))
+}
+
+/// Fails if any entry pays a recipient outside of the allowlist, as that can only be caused by a
+/// computation bug.
+fn ensure_allowlisted(reward_entries: &[RewardEntry], allowlist: &HashSet<Address>) -> Result<()> {
+    let unexpected_recipients = reward_entries
+        .iter()
+        .filter(|entry| !allowlist.contains(&entry.recipient))
+        .map(|entry| to_checksum(&entry.recipient, None))
+        .collect::<Vec<_>>();
+
+    if !unexpected_recipients.is_empty() {
+        anyhow::bail!(
+            "recipients not in allowlist: {}",
+            unexpected_recipients.join(", ")
+        );
+    }
+
+    Ok(())
 }
 
 fn parse_sha256_sum(value: &str) -> Result<[u8; 32]> {
@@ -353,3 +3078,573 @@ fn parse_sha256_sum(value: &str) -> Result<[u8; 32]> {
 
     Ok(buffer)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reward_entry(recipient: u64) -> RewardEntry {
+        RewardEntry {
+            chain_id: 1,
+            period_id: 7,
+            recipient: Address::from_low_u64_be(recipient),
+            staking_reward: U256::from(100),
+            fee_reward: U256::from(20),
+            nonce: None,
+        }
+    }
+
+    fn test_wallet() -> Wallet {
+        Wallet::LocalWallet(
+            "4c0883a69102937d6231471b5dbb6204fe5129617082792ae468d01a3f362318"
+                .parse()
+                .unwrap(),
+        )
+    }
+
+    fn no_retry() -> SignRetry {
+        SignRetry {
+            max_attempts: 1,
+            delay: Duration::ZERO,
+        }
+    }
+
+    async fn sign_entries(reward_entries: Vec<RewardEntry>) -> Result<Vec<SignedRewardEntry>> {
+        sign_entries_with(reward_entries, &[&test_wallet()]).await
+    }
+
+    async fn sign_entries_with(
+        reward_entries: Vec<RewardEntry>,
+        signers: &[&Wallet],
+    ) -> Result<Vec<SignedRewardEntry>> {
+        let domains = RewardDomains::new(
+            reward_domain(Some(1), "RewardSystem", Address::zero()),
+            vec![],
+        )
+        .unwrap();
+
+        sign_rewards(
+            reward_entries,
+            signers,
+            SignatureScheme::Eip712,
+            1,
+            &domains,
+            &HashMap::new(),
+            no_retry(),
+        )
+        .await
+    }
+
+    #[tokio::test]
+    async fn signed_entries_are_ordered_independently_of_the_input() {
+        let ascending = sign_entries((1..=4).map(reward_entry).collect())
+            .await
+            .unwrap();
+        let shuffled = sign_entries([3, 1, 4, 2].map(reward_entry).to_vec())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            serde_json::to_string(&ascending).unwrap(),
+            serde_json::to_string(&shuffled).unwrap()
+        );
+        assert_eq!(
+            ascending
+                .iter()
+                .map(|entry| entry.reward.recipient)
+                .collect::<Vec<_>>(),
+            (1..=4).map(Address::from_low_u64_be).collect::<Vec<_>>()
+        );
+    }
+
+    #[tokio::test]
+    async fn rotation_overlap_signs_with_both_keys() {
+        let primary = test_wallet();
+        let secondary = Wallet::LocalWallet(
+            "59c6995e998f97a5a0044966f0945389dc9e86dae88c7a8412f4603b6b78690d"
+                .parse()
+                .unwrap(),
+        );
+        let domain = reward_domain(Some(1), "RewardSystem", Address::zero());
+
+        let signed_entries = sign_entries_with(vec![reward_entry(1)], &[&primary, &secondary])
+            .await
+            .unwrap();
+
+        let signatures = &signed_entries[0].signatures;
+        let mut expected_signers = vec![primary.address(), secondary.address()];
+        expected_signers.sort();
+        assert_ne!(expected_signers[0], expected_signers[1]);
+        assert_eq!(
+            signatures
+                .iter()
+                .map(|signature| signature.signer)
+                .collect::<Vec<_>>(),
+            expected_signers
+        );
+        for signature in signatures {
+            let recovered = Wallet::recover_reward_signer(
+                &signed_entries[0].reward,
+                &domain,
+                &ethers::types::Signature::try_from(signature.signature.as_slice()).unwrap(),
+            )
+            .unwrap();
+            assert_eq!(recovered, signature.signer);
+        }
+    }
+
+    #[tokio::test]
+    async fn entry_for_another_chain_is_rejected_before_signing() {
+        let mut foreign_entry = reward_entry(2);
+        foreign_entry.chain_id = 56;
+
+        let err = sign_entries(vec![reward_entry(1), foreign_entry])
+            .await
+            .unwrap_err();
+
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "entries with chain id other than 1: {}",
+                to_checksum(&Address::from_low_u64_be(2), None)
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn attestation_signs_the_period_attestation_digest() {
+        let wallet = test_wallet();
+        let domain = reward_domain(Some(1), "RewardSystem", Address::from_low_u64_be(0xbeef));
+        let reward_entries = vec![reward_entry(1), reward_entry(2)];
+
+        let attestation = sign_period_attestation(7, &reward_entries, &wallet, &domain, no_retry())
+            .await
+            .unwrap();
+
+        assert_eq!(attestation.total_staking_reward, U256::from(200));
+        assert_eq!(attestation.total_fee_reward, U256::from(40));
+
+        // Same message through the generic EIP-712 encoder of ethers
+        let typed_data: ethers::types::transaction::eip712::TypedData =
+            serde_json::from_value(serde_json::json!({
+                "types": {
+                    "EIP712Domain": [
+                        { "name": "name", "type": "string" },
+                        { "name": "version", "type": "string" },
+                        { "name": "chainId", "type": "uint256" },
+                        { "name": "verifyingContract", "type": "address" },
+                    ],
+                    "PeriodAttestation": [
+                        { "name": "periodId", "type": "uint256" },
+                        { "name": "entriesHash", "type": "bytes32" },
+                        { "name": "totalStakingReward", "type": "uint256" },
+                        { "name": "totalFeeReward", "type": "uint256" },
+                    ],
+                },
+                "primaryType": "PeriodAttestation",
+                "domain": domain,
+                "message": {
+                    "periodId": 7,
+                    "entriesHash": format!("0x{}", encode(&attestation.entries_hash)),
+                    "totalStakingReward": "200",
+                    "totalFeeReward": "40",
+                },
+            }))
+            .unwrap();
+        let signature =
+            ethers::types::Signature::try_from(attestation.signature.as_slice()).unwrap();
+
+        assert_eq!(
+            signature
+                .recover(typed_data.encode_eip712().unwrap())
+                .unwrap(),
+            wallet.address()
+        );
+        assert_eq!(
+            attestation.entries_hash,
+            keccak256(
+                reward_entries
+                    .iter()
+                    .flat_map(reward_struct_hash)
+                    .collect::<Vec<_>>()
+            )
+        );
+    }
+
+    async fn fetch_mocked_chain_id(chain_id: u64, expected_chain_id: Option<u64>) -> Result<u64> {
+        let (provider, mock) = Provider::mocked();
+        mock.push(U64::from(chain_id)).unwrap();
+
+        fetch_chain_id(&provider, expected_chain_id).await
+    }
+
+    #[tokio::test]
+    async fn node_chain_id_is_validated() {
+        assert_eq!(fetch_mocked_chain_id(56, None).await.unwrap(), 56);
+        assert_eq!(fetch_mocked_chain_id(56, Some(56)).await.unwrap(), 56);
+        assert_eq!(
+            fetch_mocked_chain_id(0, None)
+                .await
+                .unwrap_err()
+                .to_string(),
+            "node reported chain id 0"
+        );
+        assert_eq!(
+            fetch_mocked_chain_id(1, Some(56))
+                .await
+                .unwrap_err()
+                .to_string(),
+            "node reported chain id 1 but 56 is expected"
+        );
+    }
+
+    #[tokio::test]
+    async fn forward_request_digest_follows_the_forwarder_domain() {
+        let wallet = test_wallet();
+        let domain = EIP712Domain {
+            name: Some(String::from("MinimalForwarder")),
+            version: Some(String::from("0.0.1")),
+            chain_id: Some(56.into()),
+            verifying_contract: Some(Address::from_low_u64_be(0xf0)),
+            salt: None,
+        };
+        let request = ForwardRequest {
+            from: wallet.address(),
+            to: Address::from_low_u64_be(0xbeef),
+            value: U256::zero(),
+            gas: U256::from(200_000),
+            nonce: U256::from(3),
+            data: vec![0xde, 0xad, 0xbe, 0xef],
+        };
+        let payload = Eip712ForwardRequest {
+            inner: &request,
+            domain: &domain,
+        };
+
+        // Same message through the generic EIP-712 encoder of ethers
+        let typed_data: ethers::types::transaction::eip712::TypedData =
+            serde_json::from_value(serde_json::json!({
+                "types": {
+                    "EIP712Domain": [
+                        { "name": "name", "type": "string" },
+                        { "name": "version", "type": "string" },
+                        { "name": "chainId", "type": "uint256" },
+                        { "name": "verifyingContract", "type": "address" },
+                    ],
+                    "ForwardRequest": [
+                        { "name": "from", "type": "address" },
+                        { "name": "to", "type": "address" },
+                        { "name": "value", "type": "uint256" },
+                        { "name": "gas", "type": "uint256" },
+                        { "name": "nonce", "type": "uint256" },
+                        { "name": "data", "type": "bytes" },
+                    ],
+                },
+                "primaryType": "ForwardRequest",
+                "domain": domain,
+                "message": {
+                    "from": format!("{:?}", request.from),
+                    "to": format!("{:?}", request.to),
+                    "value": "0",
+                    "gas": "200000",
+                    "nonce": "3",
+                    "data": "0xdeadbeef",
+                },
+            }))
+            .unwrap();
+        let digest = payload.encode_eip712().unwrap();
+        assert_eq!(digest, typed_data.encode_eip712().unwrap());
+
+        let signature = wallet.sign_typed_data(&payload).await.unwrap();
+        assert_eq!(signature.recover(digest).unwrap(), wallet.address());
+    }
+
+    fn at(seconds: u64) -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(seconds)
+    }
+
+    #[tokio::test]
+    async fn pool_fees_include_the_window_start_and_exclude_its_end() {
+        let window = (at(100), at(200));
+        let exchange_entry = |seconds: u64, fee_for_pool: u64| ExchangeEntry {
+            id: seconds.to_string(),
+            index: seconds,
+            from_addr: Address::zero(),
+            source_key: String::from("lUSD"),
+            source_amount: U256::zero(),
+            dest_addr: Address::zero(),
+            dest_key: String::from("lBTC"),
+            dest_recived: U256::zero(),
+            fee_for_pool: fee_for_pool.into(),
+            fee_for_foundation: U256::zero(),
+            timestamp: at(seconds),
+        };
+        let perp_fee_entry = |seconds: u64, fee_for_pool: u64| PerpFeeEntry {
+            id: seconds.to_string(),
+            index: seconds,
+            fee_for_pool: fee_for_pool.into(),
+            fee_for_foundation: U256::zero(),
+            timestamp: at(seconds),
+        };
+
+        let exchange_fees = sum_pool_fees(
+            futures::stream::iter(
+                [(99, 1), (100, 10), (199, 100), (200, 1000)]
+                    .map(|(seconds, fee)| Ok(exchange_entry(seconds, fee))),
+            ),
+            window,
+        )
+        .await
+        .unwrap();
+        let perp_fees = sum_pool_fees(
+            futures::stream::iter(
+                [(100, 2), (150, 20), (200, 200)]
+                    .map(|(seconds, fee)| Ok(perp_fee_entry(seconds, fee))),
+            ),
+            window,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(exchange_fees, U256::from(110));
+        assert_eq!(perp_fees, U256::from(22));
+    }
+
+    #[tokio::test]
+    async fn eip191_signature_has_electrum_v() {
+        let wallet: LocalWallet =
+            "0x4c0883a69102937d6231471b5dbb6204fe5129617082792ae468d01a3f362318"
+                .parse::<LocalWallet>()
+                .unwrap()
+                .with_chain_id(1337u64);
+        let message = reward_packed_hash(&reward_entry(1)).unwrap();
+        let signature = wallet.sign_message(message).await.unwrap();
+
+        // What an EIP-155 signer would have returned for the same recovery id
+        let eip155 = ethers::types::Signature {
+            v: 1337 * 2 + 35 + (signature.v - 27),
+            ..signature
+        };
+        let normalized = to_electrum_v(eip155).unwrap();
+
+        assert_eq!(normalized, signature);
+        assert!(normalized.v == 27 || normalized.v == 28);
+        assert_eq!(
+            normalized.recover(hash_message(message)).unwrap(),
+            wallet.address()
+        );
+    }
+
+    #[test]
+    fn electrum_v_is_kept() {
+        let signature = ethers::types::Signature {
+            r: U256::one(),
+            s: U256::one(),
+            v: 0,
+        };
+        assert_eq!(to_electrum_v(signature).unwrap().v, 27);
+        assert_eq!(
+            to_electrum_v(ethers::types::Signature { v: 28, ..signature })
+                .unwrap()
+                .v,
+            28
+        );
+        assert_eq!(
+            to_electrum_v(ethers::types::Signature { v: 38, ..signature })
+                .unwrap()
+                .v,
+            28
+        );
+    }
+
+    #[test]
+    fn malformed_v_is_rejected() {
+        let signature = ethers::types::Signature {
+            r: U256::one(),
+            s: U256::one(),
+            v: 0,
+        };
+        for v in [2, 26, 29, 34] {
+            assert_eq!(
+                to_electrum_v(ethers::types::Signature { v, ..signature })
+                    .unwrap_err()
+                    .to_string(),
+                format!("malformed signature v: {v}")
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn batch_signature_passes_recovery_check() {
+        let wallet = test_wallet();
+        let domain = reward_domain(Some(1), "RewardSystem", Address::zero());
+
+        let batch = sign_reward_batch(
+            vec![reward_entry(2), reward_entry(1)],
+            &wallet,
+            1,
+            &domain,
+            no_retry(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            batch.recipients,
+            vec![Address::from_low_u64_be(1), Address::from_low_u64_be(2)]
+        );
+        assert_eq!(batch.signature.signer, wallet.address());
+    }
+
+    #[test]
+    fn foreign_recovered_signer_is_rejected() {
+        let signer = Address::from_low_u64_be(1);
+
+        assert!(ensure_recovered_signer(signer, signer, "batch").is_ok());
+        assert!(ensure_recovered_signer(Address::zero(), signer, "batch").is_err());
+    }
+
+    #[test]
+    fn excluded_share_goes_to_the_remaining_stakers() {
+        let stakers = [1, 2, 3].map(Address::from_low_u64_be);
+        let weights = stakers
+            .iter()
+            .map(|staker| (*staker, U256::from(10)))
+            .collect::<HashMap<_, _>>();
+
+        let weights = eligible_weights(
+            weights,
+            &HashMap::new(),
+            &HashSet::from([stakers[1]]),
+            &HashSet::new(),
+        );
+        let split = split_pool(U256::from(900), &weights, true).unwrap();
+
+        assert_eq!(
+            split.shares,
+            vec![(stakers[0], U256::from(450)), (stakers[2], U256::from(450))]
+        );
+    }
+
+    #[test]
+    fn delegated_weights_are_merged_before_the_split() {
+        let owner = Address::from_low_u64_be(1);
+        let other_owner = Address::from_low_u64_be(2);
+        let delegate = Address::from_low_u64_be(3);
+        let excluded = Address::from_low_u64_be(4);
+        let weights = HashMap::from([
+            (owner, U256::from(10)),
+            (other_owner, U256::from(5)),
+            (delegate, U256::from(1)),
+            (excluded, U256::from(7)),
+        ]);
+        let delegations = HashMap::from([
+            (owner, delegate),
+            (other_owner, delegate),
+            (excluded, excluded),
+            (delegate, excluded),
+        ]);
+
+        let merged = apply_delegations(weights, &delegations, &HashSet::from([excluded]));
+
+        // Delegating to an excluded address leaves the weight with its owner
+        assert_eq!(
+            merged,
+            HashMap::from([(delegate, U256::from(16)), (excluded, U256::from(7))])
+        );
+    }
+
+    /// Time of each step of the reward computation for `staker_count` synthetic stakers with one
+    /// debt entry and one fee entry each.
+    async fn time_reward_computation(staker_count: u64) -> [Duration; 3] {
+        const UNIT: u64 = 1_000_000_000_000_000_000;
+        let window = (at(0), at(staker_count * 2));
+        let debt_entries = (0..staker_count)
+            .map(|index| DebtEntry {
+                id: index.to_string(),
+                index,
+                address: Address::from_low_u64_be(index + 1),
+                debt_factor: U256::from(UNIT),
+                debt_proportion: U256::from(UNIT / staker_count),
+                timestamp: at(index),
+            })
+            .collect::<Vec<_>>();
+        let fee_entries = (0..staker_count).map(|index| {
+            Ok(PerpFeeEntry {
+                id: index.to_string(),
+                index,
+                fee_for_pool: U256::from(1_000_000_007u64),
+                fee_for_foundation: U256::zero(),
+                timestamp: at(index),
+            })
+        });
+
+        let started_at = Instant::now();
+        let weights = crate::weights::TimeWeightedStrategy.weights(&debt_entries, window);
+        let weight_time = started_at.elapsed();
+
+        let started_at = Instant::now();
+        let fees_accumulated = sum_pool_fees(futures::stream::iter(fee_entries), window)
+            .await
+            .unwrap();
+        let fee_time = started_at.elapsed();
+
+        let started_at = Instant::now();
+        let composition = RewardComposition {
+            scheduled_staking_rewards: U256::exp10(24),
+            fees_accumulated,
+            ..Default::default()
+        };
+        let weights = eligible_weights(weights, &HashMap::new(), &HashSet::new(), &HashSet::new());
+        let allocation = |pool: U256| {
+            let split = split_pool(pool, &weights, true).unwrap();
+            PoolAllocation {
+                allocated: pool - split.undistributed,
+                dust: split.dust,
+                undistributed: split.undistributed,
+            }
+        };
+        ReconciliationReport::new(
+            7,
+            &composition,
+            allocation(composition.staking_reward_for_period()),
+            allocation(composition.fee_reward_for_period()),
+        )
+        .ensure_balanced()
+        .unwrap();
+        let split_time = started_at.elapsed();
+
+        [weight_time, fee_time, split_time]
+    }
+
+    /// Run with `cargo test --release -- --ignored --nocapture reward_computation_scales` to size
+    /// instances. Fails if a step grows much faster than linearly between the two largest scales.
+    #[tokio::test]
+    #[ignore = "benchmark"]
+    async fn reward_computation_scales() {
+        let scales = [1_000, 100_000, 1_000_000];
+        let mut per_staker = vec![];
+        for staker_count in scales {
+            let times = time_reward_computation(staker_count).await;
+            println!(
+                "{staker_count} stakers: weights {:?}, fees {:?}, split and reconciliation {:?}",
+                times[0], times[1], times[2]
+            );
+            per_staker.push(times.map(|time| time.as_secs_f64() / staker_count as f64));
+        }
+
+        let (middle, largest) = (&per_staker[1], &per_staker[2]);
+        for (step, name) in ["weights", "fees", "split and reconciliation"]
+            .into_iter()
+            .enumerate()
+        {
+            assert!(
+                largest[step] < middle[step] * 5.0,
+                "{name} take {:.2}x as long per staker at {} as at {} stakers",
+                largest[step] / middle[step],
+                scales[2],
+                scales[1]
+            );
+        }
+    }
+}
@@ -0,0 +1,109 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use ethers::prelude::*;
+use log::{debug, warn};
+use reqwest::{Client as HttpClient, ClientBuilder, Url};
+use serde::Serialize;
+
+use crate::custom_serde::u256_dec_opt;
+
+/// Posts a summary of each processing cycle to a webhook.
+pub struct Notifier {
+    client: HttpClient,
+    url: Url,
+    notify_on_success: bool,
+}
+
+/// Outcome of a successful cycle.
+#[derive(Debug, Clone)]
+pub struct CycleSummary {
+    pub period_id: u32,
+    pub entry_count: usize,
+    pub total_staking_reward: U256,
+    pub total_fee_reward: U256,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct CycleNotification {
+    /// Plain-text summary, which is what Slack-compatible webhooks display.
+    text: String,
+    success: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    period_id: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    entry_count: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none", with = "u256_dec_opt")]
+    total_staking_reward: Option<U256>,
+    #[serde(skip_serializing_if = "Option::is_none", with = "u256_dec_opt")]
+    total_fee_reward: Option<U256>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+impl Notifier {
+    pub fn new(url: Url, timeout: Duration, notify_on_success: bool) -> Self {
+        Self {
+            client: ClientBuilder::new().timeout(timeout).build().unwrap(),
+            url,
+            notify_on_success,
+        }
+    }
+
+    /// Sends the notification for a cycle result. Failures are only logged so that a broken webhook
+    /// never fails the cycle itself. Skipped cycles have nothing to report.
+    pub async fn notify(&self, result: &Result<Option<CycleSummary>>) {
+        let notification = match result {
+            Ok(None) => return,
+            Ok(Some(summary)) => {
+                if !self.notify_on_success {
+                    return;
+                }
+
+                CycleNotification {
+                    text: format!(
+                        "Signed {} reward entries for period {}",
+                        summary.entry_count, summary.period_id
+                    ),
+                    success: true,
+                    period_id: Some(summary.period_id),
+                    entry_count: Some(summary.entry_count),
+                    total_staking_reward: Some(summary.total_staking_reward),
+                    total_fee_reward: Some(summary.total_fee_reward),
+                    error: None,
+                }
+            }
+            Err(err) => CycleNotification {
+                text: format!("Reward signing cycle failed: {err}"),
+                success: false,
+                period_id: None,
+                entry_count: None,
+                total_staking_reward: None,
+                total_fee_reward: None,
+                error: Some(err.to_string()),
+            },
+        };
+
+        match self.send(&notification).await {
+            Ok(_) => debug!("Sent cycle notification"),
+            Err(err) => warn!("Unable to send cycle notification: {err}"),
+        }
+    }
+
+    async fn send(&self, notification: &CycleNotification) -> Result<()> {
+        let response = self
+            .client
+            .post(self.url.clone())
+            .json(notification)
+            .send()
+            .await?;
+
+        let status_code = response.status();
+        if !status_code.is_success() {
+            anyhow::bail!("unsuccessful status code: {}", status_code);
+        }
+
+        Ok(())
+    }
+}
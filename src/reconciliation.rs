@@ -0,0 +1,214 @@
+use std::path::Path;
+
+use anyhow::Result;
+use ethers::prelude::*;
+use log::{error, info};
+use serde::Serialize;
+
+use crate::{
+    custom_serde::{i256_dec, u256_dec},
+    util::{format_signed_units_for_log, format_units_for_log},
+    worker::RewardComposition,
+};
+
+/// Side-by-side view of what a period should pay out according to its composition and what the
+/// split actually allocated, as the last check before a submission is staged.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReconciliationReport {
+    pub period_id: u32,
+    #[serde(with = "u256_dec")]
+    pub scheduled_staking_rewards: U256,
+    #[serde(with = "u256_dec")]
+    pub rollover_staking_rewards: U256,
+    #[serde(with = "u256_dec")]
+    pub fees_accumulated: U256,
+    #[serde(with = "u256_dec")]
+    pub rollover_fees: U256,
+    #[serde(with = "i256_dec")]
+    pub staking_adjustment: I256,
+    #[serde(with = "i256_dec")]
+    pub fee_adjustment: I256,
+    #[serde(with = "u256_dec")]
+    pub expected_staking_reward: U256,
+    #[serde(with = "u256_dec")]
+    pub expected_fee_reward: U256,
+    #[serde(with = "u256_dec")]
+    pub allocated_staking_reward: U256,
+    #[serde(with = "u256_dec")]
+    pub allocated_fee_reward: U256,
+    /// Rounding dust included in the allocated amounts.
+    #[serde(with = "u256_dec")]
+    pub staking_dust: U256,
+    #[serde(with = "u256_dec")]
+    pub fee_dust: U256,
+    /// Amounts rolled over to the next period because nobody could receive them.
+    #[serde(with = "u256_dec")]
+    pub undistributed_staking_reward: U256,
+    #[serde(with = "u256_dec")]
+    pub undistributed_fee_reward: U256,
+    /// Expected minus allocated and undistributed. Anything but zero is unexplained.
+    #[serde(with = "i256_dec")]
+    pub staking_difference: I256,
+    #[serde(with = "i256_dec")]
+    pub fee_difference: I256,
+}
+
+/// Totals of one pool after the split.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PoolAllocation {
+    pub allocated: U256,
+    pub dust: U256,
+    pub undistributed: U256,
+}
+
+impl ReconciliationReport {
+    pub fn new(
+        period_id: u32,
+        composition: &RewardComposition,
+        staking: PoolAllocation,
+        fees: PoolAllocation,
+    ) -> Self {
+        let expected_staking_reward = composition.staking_reward_for_period();
+        let expected_fee_reward = composition.fee_reward_for_period();
+
+        Self {
+            period_id,
+            scheduled_staking_rewards: composition.scheduled_staking_rewards,
+            rollover_staking_rewards: composition.rollover_staking_rewards,
+            fees_accumulated: composition.fees_accumulated,
+            rollover_fees: composition.rollover_fees,
+            staking_adjustment: composition.staking_adjustment,
+            fee_adjustment: composition.fee_adjustment,
+            expected_staking_reward,
+            expected_fee_reward,
+            allocated_staking_reward: staking.allocated,
+            allocated_fee_reward: fees.allocated,
+            staking_dust: staking.dust,
+            fee_dust: fees.dust,
+            undistributed_staking_reward: staking.undistributed,
+            undistributed_fee_reward: fees.undistributed,
+            staking_difference: unexplained_difference(expected_staking_reward, staking),
+            fee_difference: unexplained_difference(expected_fee_reward, fees),
+        }
+    }
+
+    pub fn log(&self, display_decimals: u32) {
+        let amount = |value: U256| format_units_for_log(value, display_decimals);
+        let signed_amount = |value: I256| format_signed_units_for_log(value, display_decimals);
+
+        info!("Reconciliation for period {}:", self.period_id);
+        info!(
+            "  staking: scheduled {} + rollover {} + adjustment {} = {}; allocated {} (dust {}), undistributed {}, difference {}",
+            amount(self.scheduled_staking_rewards),
+            amount(self.rollover_staking_rewards),
+            signed_amount(self.staking_adjustment),
+            amount(self.expected_staking_reward),
+            amount(self.allocated_staking_reward),
+            amount(self.staking_dust),
+            amount(self.undistributed_staking_reward),
+            signed_amount(self.staking_difference)
+        );
+        info!(
+            "  fees: accumulated {} + rollover {} + adjustment {} = {}; allocated {} (dust {}), undistributed {}, difference {}",
+            amount(self.fees_accumulated),
+            amount(self.rollover_fees),
+            signed_amount(self.fee_adjustment),
+            amount(self.expected_fee_reward),
+            amount(self.allocated_fee_reward),
+            amount(self.fee_dust),
+            amount(self.undistributed_fee_reward),
+            signed_amount(self.fee_difference)
+        );
+    }
+
+    pub fn write(&self, path: &Path) -> Result<()> {
+        std::fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// Fails on any difference that isn't explained by the allocation or the rollover.
+    pub fn ensure_balanced(&self) -> Result<()> {
+        if self.staking_difference.is_zero() && self.fee_difference.is_zero() {
+            return Ok(());
+        }
+
+        error!(
+            "Unexplained difference in period {}: staking {}, fees {}",
+            self.period_id, self.staking_difference, self.fee_difference
+        );
+        anyhow::bail!(
+            "allocated rewards of period {} don't reconcile with its composition",
+            self.period_id
+        );
+    }
+}
+
+fn unexplained_difference(expected: U256, allocation: PoolAllocation) -> I256 {
+    let accounted = allocation
+        .allocated
+        .checked_add(allocation.undistributed)
+        .expect("overflow");
+    if expected >= accounted {
+        I256::try_from(expected - accounted).expect("overflow")
+    } else {
+        -I256::try_from(accounted - expected).expect("overflow")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn composition() -> RewardComposition {
+        RewardComposition {
+            scheduled_staking_rewards: U256::from(100),
+            rollover_staking_rewards: U256::from(10),
+            fees_accumulated: U256::from(50),
+            rollover_fees: U256::from(5),
+            staking_adjustment: I256::from(-10),
+            fee_adjustment: I256::from(0),
+        }
+    }
+
+    #[test]
+    fn allocation_matching_composition_is_balanced() {
+        let report = ReconciliationReport::new(
+            1,
+            &composition(),
+            PoolAllocation {
+                allocated: U256::from(100),
+                dust: U256::from(1),
+                undistributed: U256::zero(),
+            },
+            PoolAllocation {
+                allocated: U256::from(40),
+                dust: U256::zero(),
+                undistributed: U256::from(15),
+            },
+        );
+
+        assert!(report.staking_difference.is_zero());
+        assert!(report.fee_difference.is_zero());
+        report.ensure_balanced().unwrap();
+    }
+
+    #[test]
+    fn unexplained_difference_is_rejected() {
+        let report = ReconciliationReport::new(
+            1,
+            &composition(),
+            PoolAllocation {
+                allocated: U256::from(101),
+                ..Default::default()
+            },
+            PoolAllocation {
+                allocated: U256::from(55),
+                ..Default::default()
+            },
+        );
+
+        assert_eq!(report.staking_difference, I256::from(-1));
+        assert!(report.ensure_balanced().is_err());
+    }
+}
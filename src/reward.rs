@@ -0,0 +1,350 @@
+use anyhow::Result;
+use ethers::{
+    abi::{self, Token},
+    prelude::*,
+    types::transaction::eip712::{EIP712Domain, Eip712},
+    utils::keccak256,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::custom_serde::{checksumed_address, u256_dec, u256_dec_opt};
+
+pub const REWARD_TYPE: &str =
+    "Reward(uint256 periodId,address recipient,uint256 stakingReward,uint256 feeReward)";
+pub const REWARD_WITH_NONCE_TYPE: &str =
+    "Reward(uint256 periodId,address recipient,uint256 stakingReward,uint256 feeReward,uint256 nonce)";
+pub const REWARD_BATCH_TYPE: &str = "RewardBatch(Reward[] rewards)";
+
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RewardEntry {
+    pub chain_id: u64,
+    pub period_id: u32,
+    #[serde(with = "checksumed_address")]
+    pub recipient: Address,
+    #[serde(with = "u256_dec")]
+    pub staking_reward: U256,
+    #[serde(with = "u256_dec")]
+    pub fee_reward: U256,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "u256_dec_opt"
+    )]
+    pub nonce: Option<U256>,
+}
+
+impl PartialOrd for RewardEntry {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for RewardEntry {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.recipient.cmp(&other.recipient)
+    }
+}
+
+/// Builds the domain of reward signatures. `chain_id` is `None` for cross-chain messages; the signer
+/// keeps its own chain id regardless.
+pub fn reward_domain(
+    chain_id: Option<u64>,
+    contract_name: &str,
+    contract_address: Address,
+) -> EIP712Domain {
+    EIP712Domain {
+        name: Some(contract_name.to_owned()),
+        version: Some("1".into()),
+        chain_id: chain_id.map(Into::into),
+        verifying_contract: Some(contract_address),
+        salt: None,
+    }
+}
+
+/// EIP-712 encoding of a single entry, shared by signing and signature recovery.
+pub struct Eip712RewardEntry<'a> {
+    pub inner: &'a RewardEntry,
+    pub domain: &'a EIP712Domain,
+}
+
+impl<'a> Eip712 for Eip712RewardEntry<'a> {
+    type Error = std::convert::Infallible;
+
+    fn domain(&self) -> std::result::Result<EIP712Domain, Self::Error> {
+        Ok(self.domain.clone())
+    }
+
+    fn type_hash() -> std::result::Result<[u8; 32], Self::Error> {
+        Ok(keccak256(REWARD_TYPE))
+    }
+
+    fn struct_hash(&self) -> std::result::Result<[u8; 32], Self::Error> {
+        Ok(reward_struct_hash(self.inner))
+    }
+}
+
+/// EIP-712 encoding of all entries of a period as the `Reward[]` member of a `RewardBatch` struct.
+pub struct Eip712RewardBatch<'a> {
+    pub entries: &'a [RewardEntry],
+    pub domain: &'a EIP712Domain,
+}
+
+impl<'a> Eip712 for Eip712RewardBatch<'a> {
+    type Error = std::convert::Infallible;
+
+    fn domain(&self) -> std::result::Result<EIP712Domain, Self::Error> {
+        Ok(self.domain.clone())
+    }
+
+    fn type_hash() -> std::result::Result<[u8; 32], Self::Error> {
+        Ok(keccak256(format!("{REWARD_BATCH_TYPE}{REWARD_TYPE}")))
+    }
+
+    fn struct_hash(&self) -> std::result::Result<[u8; 32], Self::Error> {
+        let type_hash = match self.entries.first() {
+            Some(entry) if entry.nonce.is_some() => {
+                keccak256(format!("{REWARD_BATCH_TYPE}{REWARD_WITH_NONCE_TYPE}"))
+            }
+            _ => Self::type_hash()?,
+        };
+
+        // Arrays are encoded as the hash of the concatenated element struct hashes
+        let element_hashes = self
+            .entries
+            .iter()
+            .flat_map(reward_struct_hash)
+            .collect::<Vec<_>>();
+
+        Ok(keccak256(abi::encode(&[
+            Token::Uint(U256::from(type_hash)),
+            Token::FixedBytes(keccak256(element_hashes).to_vec()),
+        ])))
+    }
+}
+
+pub fn reward_struct_hash(entry: &RewardEntry) -> [u8; 32] {
+    let mut tokens = vec![
+        Token::Uint(U256::from(keccak256(REWARD_TYPE))),
+        Token::Uint(entry.period_id.into()),
+        Token::Address(entry.recipient),
+        Token::Uint(entry.staking_reward),
+        Token::Uint(entry.fee_reward),
+    ];
+
+    // Entries carrying a nonce are signed with the v2 struct
+    if let Some(nonce) = entry.nonce {
+        tokens[0] = Token::Uint(U256::from(keccak256(REWARD_WITH_NONCE_TYPE)));
+        tokens.push(Token::Uint(nonce));
+    }
+
+    keccak256(abi::encode(&tokens))
+}
+
+/// `keccak256(abi.encodePacked(periodId, recipient, stakingReward, feeReward))`, which legacy
+/// verifiers check against an EIP-191 `personal_sign` signature.
+pub fn reward_packed_hash(entry: &RewardEntry) -> Result<[u8; 32]> {
+    Ok(keccak256(abi::encode_packed(&[
+        Token::Uint(entry.period_id.into()),
+        Token::Address(entry.recipient),
+        Token::Uint(entry.staking_reward),
+        Token::Uint(entry.fee_reward),
+    ])?))
+}
+
+#[cfg(test)]
+mod tests {
+    use ethers::types::transaction::eip712::TypedData;
+    use serde_json::json;
+
+    use super::*;
+
+    const REWARD_FIELDS: &str = r#"[
+        { "name": "periodId", "type": "uint256" },
+        { "name": "recipient", "type": "address" },
+        { "name": "stakingReward", "type": "uint256" },
+        { "name": "feeReward", "type": "uint256" }
+    ]"#;
+
+    fn entry(recipient: u64, nonce: Option<u64>) -> RewardEntry {
+        RewardEntry {
+            chain_id: 1,
+            period_id: 7,
+            recipient: Address::from_low_u64_be(recipient),
+            staking_reward: U256::exp10(18) * 3,
+            fee_reward: U256::exp10(17),
+            nonce: nonce.map(U256::from),
+        }
+    }
+
+    fn domain() -> EIP712Domain {
+        reward_domain(Some(1), "RewardSystem", Address::from_low_u64_be(0xbeef))
+    }
+
+    /// Digest of the same message as computed by the generic EIP-712 encoder of ethers, which works
+    /// off the JSON type definitions like wallets and `eth_signTypedData` do.
+    fn typed_data_digest(
+        domain: &EIP712Domain,
+        types: serde_json::Value,
+        primary_type: &str,
+        message: serde_json::Value,
+    ) -> [u8; 32] {
+        let mut domain_fields = vec![
+            json!({ "name": "name", "type": "string" }),
+            json!({ "name": "version", "type": "string" }),
+        ];
+        if domain.chain_id.is_some() {
+            domain_fields.push(json!({ "name": "chainId", "type": "uint256" }));
+        }
+        domain_fields.push(json!({ "name": "verifyingContract", "type": "address" }));
+
+        let mut types = types;
+        types["EIP712Domain"] = json!(domain_fields);
+
+        let typed_data: TypedData = serde_json::from_value(json!({
+            "types": types,
+            "primaryType": primary_type,
+            "domain": domain,
+            "message": message,
+        }))
+        .unwrap();
+        typed_data.encode_eip712().unwrap()
+    }
+
+    fn reward_message(entry: &RewardEntry) -> serde_json::Value {
+        let mut message = json!({
+            "periodId": entry.period_id,
+            "recipient": format!("{:?}", entry.recipient),
+            "stakingReward": entry.staking_reward.to_string(),
+            "feeReward": entry.fee_reward.to_string(),
+        });
+        if let Some(nonce) = entry.nonce {
+            message["nonce"] = json!(nonce.to_string());
+        }
+        message
+    }
+
+    fn reward_fields(with_nonce: bool) -> serde_json::Value {
+        let mut fields: serde_json::Value = serde_json::from_str(REWARD_FIELDS).unwrap();
+        if with_nonce {
+            fields
+                .as_array_mut()
+                .unwrap()
+                .push(json!({ "name": "nonce", "type": "uint256" }));
+        }
+        fields
+    }
+
+    #[test]
+    fn nonce_is_part_of_the_signed_struct() {
+        let domain = domain();
+        let with_nonce = entry(1, Some(5));
+        let digest = Eip712RewardEntry {
+            inner: &with_nonce,
+            domain: &domain,
+        }
+        .encode_eip712()
+        .unwrap();
+
+        assert_eq!(
+            digest,
+            typed_data_digest(
+                &domain,
+                json!({ "Reward": reward_fields(true) }),
+                "Reward",
+                reward_message(&with_nonce),
+            )
+        );
+
+        let other_nonce = entry(1, Some(6));
+        let without_nonce = entry(1, None);
+        for other in [&other_nonce, &without_nonce] {
+            let other_digest = Eip712RewardEntry {
+                inner: other,
+                domain: &domain,
+            }
+            .encode_eip712()
+            .unwrap();
+            assert_ne!(other_digest, digest);
+        }
+    }
+
+    #[test]
+    fn batch_digest_follows_the_struct_array_encoding() {
+        let domain = domain();
+        for (with_nonce, expected_digest) in [
+            (
+                false,
+                "b163b860cc9244e1dcccbcce08d2c186558d4cdcc201a5f53bf2eab095749201",
+            ),
+            (
+                true,
+                "93649dd5568855bd284d62fc49c8f65019e2e965c9e47dce2f49ac58b9bc01a2",
+            ),
+        ] {
+            let entries = [1, 2, 3]
+                .map(|recipient| entry(recipient, with_nonce.then_some(recipient)))
+                .to_vec();
+            let digest = Eip712RewardBatch {
+                entries: &entries,
+                domain: &domain,
+            }
+            .encode_eip712()
+            .unwrap();
+
+            assert_eq!(hex::encode(digest), expected_digest);
+            assert_eq!(
+                digest,
+                typed_data_digest(
+                    &domain,
+                    json!({
+                        "RewardBatch": [{ "name": "rewards", "type": "Reward[]" }],
+                        "Reward": reward_fields(with_nonce),
+                    }),
+                    "RewardBatch",
+                    json!({ "rewards": entries.iter().map(reward_message).collect::<Vec<_>>() }),
+                )
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn domain_without_chain_id_omits_it_from_the_digest() {
+        let cross_chain_domain =
+            reward_domain(None, "RewardSystem", Address::from_low_u64_be(0xbeef));
+        let reward = entry(1, None);
+        let payload = Eip712RewardEntry {
+            inner: &reward,
+            domain: &cross_chain_domain,
+        };
+        let digest = payload.encode_eip712().unwrap();
+
+        assert_eq!(
+            digest,
+            typed_data_digest(
+                &cross_chain_domain,
+                json!({ "Reward": reward_fields(false) }),
+                "Reward",
+                reward_message(&reward),
+            )
+        );
+        assert_ne!(
+            digest,
+            Eip712RewardEntry {
+                inner: &reward,
+                domain: &domain(),
+            }
+            .encode_eip712()
+            .unwrap()
+        );
+
+        // The wallet keeps its chain id, which must not leak into the signature
+        let wallet = "4c0883a69102937d6231471b5dbb6204fe5129617082792ae468d01a3f362318"
+            .parse::<LocalWallet>()
+            .unwrap()
+            .with_chain_id(56u64);
+        let signature = wallet.sign_typed_data(&payload).await.unwrap();
+        assert!(signature.v == 27 || signature.v == 28);
+        assert_eq!(signature.recover(digest).unwrap(), wallet.address());
+    }
+}
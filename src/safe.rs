@@ -0,0 +1,98 @@
+use std::{path::Path, time::SystemTime};
+
+use anyhow::Result;
+use ethers::{
+    abi::{AbiDecode, AbiEncode},
+    prelude::*,
+    utils::to_checksum,
+};
+use serde::Serialize;
+
+use crate::{
+    contracts::ClaimRewardForCall,
+    custom_serde::{checksumed_address, hex_bytes},
+};
+
+/// Transaction batch in the format of the Safe Transaction Builder, so that claims can be relayed
+/// through a Safe without encoding calldata by hand.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SafeBatch {
+    version: &'static str,
+    chain_id: String,
+    created_at: u128,
+    meta: SafeBatchMeta,
+    transactions: Vec<SafeTransaction>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SafeBatchMeta {
+    name: String,
+    description: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SafeTransaction {
+    #[serde(serialize_with = "checksumed_address::serialize")]
+    to: Address,
+    value: String,
+    #[serde(with = "hex_bytes")]
+    data: Vec<u8>,
+}
+
+impl SafeBatch {
+    /// Builds one `claimRewardFor` transaction per claim against `reward_system`.
+    pub fn from_claims(
+        chain_id: u64,
+        reward_system: Address,
+        period_id: u32,
+        claims: Vec<ClaimRewardForCall>,
+    ) -> Result<Self> {
+        let transactions = claims
+            .into_iter()
+            .map(|claim| {
+                let data = claim.clone().encode();
+
+                // Guards against the bindings and the signed entries disagreeing on the layout
+                let decoded = ClaimRewardForCall::decode(&data)?;
+                if decoded != claim {
+                    anyhow::bail!(
+                        "claim calldata for {} does not decode to the signed entry",
+                        to_checksum(&claim.recipient, None)
+                    );
+                }
+
+                Ok(SafeTransaction {
+                    to: reward_system,
+                    value: String::from("0"),
+                    data,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Self {
+            version: "1.0",
+            chain_id: chain_id.to_string(),
+            created_at: SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)?
+                .as_millis(),
+            meta: SafeBatchMeta {
+                name: format!("Reward claims for period {period_id}"),
+                description: format!(
+                    "{} claimRewardFor calls for period {} on chain {}",
+                    transactions.len(),
+                    period_id,
+                    chain_id
+                ),
+            },
+            transactions,
+        })
+    }
+
+    pub fn write(&self, path: &Path) -> Result<()> {
+        std::fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+}
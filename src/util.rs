@@ -0,0 +1,168 @@
+use std::time::{Duration, SystemTime};
+
+use anyhow::Result;
+use ethers::types::{I256, U256};
+use rand::Rng;
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+/// Full-jitter exponential backoff: a random delay between zero and `base * 2^attempt`, with the
+/// upper bound capped at `max`.
+pub fn full_jitter_backoff(base: Duration, max: Duration, attempt: u32) -> Duration {
+    let ceiling = 2u32
+        .checked_pow(attempt)
+        .and_then(|factor| base.checked_mul(factor))
+        .map_or(max, |delay| delay.min(max));
+
+    // `thread_rng` is thread-local, so this is safe to call from any task
+    let millis = rand::thread_rng().gen_range(0..=ceiling.as_millis() as u64);
+
+    Duration::from_millis(millis)
+}
+
+/// Parses a subgraph timestamp given either as unix seconds or as an RFC 3339 string, without any
+/// local-time conversion. Fails instead of panicking on values `SystemTime` can't represent.
+pub fn parse_timestamp(value: &str) -> Result<SystemTime> {
+    let since_epoch = match value.parse::<u64>() {
+        Ok(seconds) => Duration::from_secs(seconds),
+        Err(_) => {
+            let datetime = chrono::DateTime::parse_from_rfc3339(value)
+                .map_err(|err| anyhow::anyhow!("invalid timestamp {}: {}", value, err))?;
+            let seconds = u64::try_from(datetime.timestamp())
+                .map_err(|_| anyhow::anyhow!("timestamp before unix epoch: {}", value))?;
+            Duration::new(seconds, datetime.timestamp_subsec_nanos())
+        }
+    };
+
+    SystemTime::UNIX_EPOCH
+        .checked_add(since_epoch)
+        .ok_or_else(|| anyhow::anyhow!("timestamp out of range: {}", value))
+}
+
+/// Shortens `text` to at most `max_chars` characters for inclusion in log and error messages.
+pub fn truncate_for_log(text: &str, max_chars: usize) -> String {
+    match text.char_indices().nth(max_chars) {
+        Some((end, _)) => format!("{}...", &text[..end]),
+        None => text.to_owned(),
+    }
+}
+
+/// Applies a signed `delta` to an unsigned amount. Returns `None` if the result would go below zero
+/// or overflow.
+pub fn apply_signed_delta(value: U256, delta: I256) -> Option<U256> {
+    if delta.is_negative() {
+        value.checked_sub(delta.unsigned_abs())
+    } else {
+        value.checked_add(delta.unsigned_abs())
+    }
+}
+
+/// Formats a wei amount with `decimals` decimal places for log output, trimming trailing zeros.
+/// Works on the decimal string, so no precision is lost for any `U256`.
+pub fn format_units_for_log(value: U256, decimals: u32) -> String {
+    let digits = value.to_string();
+    let decimals = decimals as usize;
+    if decimals == 0 {
+        return digits;
+    }
+
+    let digits = format!("{:0>width$}", digits, width = decimals + 1);
+    let (integer, fraction) = digits.split_at(digits.len() - decimals);
+    let fraction = fraction.trim_end_matches('0');
+    if fraction.is_empty() {
+        integer.to_owned()
+    } else {
+        format!("{integer}.{fraction}")
+    }
+}
+
+/// Signed counterpart of [`format_units_for_log`].
+pub fn format_signed_units_for_log(value: I256, decimals: u32) -> String {
+    let sign = if value.is_negative() { "-" } else { "" };
+    format!(
+        "{sign}{}",
+        format_units_for_log(value.unsigned_abs(), decimals)
+    )
+}
+
+/// Serializes `value` as compact JSON with object keys sorted at every level, so that equal values
+/// always produce identical bytes regardless of struct field order or `#[serde(flatten)]`.
+pub fn to_canonical_json<T>(value: &T) -> serde_json::Result<String>
+where
+    T: Serialize,
+{
+    fn canonicalize(value: Value) -> Value {
+        match value {
+            Value::Object(map) => {
+                let mut entries = map.into_iter().collect::<Vec<_>>();
+                entries.sort_by(|(lhs, _), (rhs, _)| lhs.cmp(rhs));
+                Value::Object(
+                    entries
+                        .into_iter()
+                        .map(|(key, value)| (key, canonicalize(value)))
+                        .collect::<Map<_, _>>(),
+                )
+            }
+            Value::Array(items) => Value::Array(items.into_iter().map(canonicalize).collect()),
+            value => value,
+        }
+    }
+
+    serde_json::to_string(&canonicalize(serde_json::to_value(value)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn full_jitter_backoff_stays_within_bounds() {
+        let base = Duration::from_millis(100);
+        let max = Duration::from_secs(5);
+
+        for attempt in 0..8 {
+            let ceiling = (base * 2u32.pow(attempt)).min(max);
+            for _ in 0..100 {
+                assert!(full_jitter_backoff(base, max, attempt) <= ceiling);
+            }
+        }
+        // Attempts far beyond the cap neither overflow nor exceed it
+        for attempt in [31, 32, 1000, u32::MAX] {
+            assert!(full_jitter_backoff(base, max, attempt) <= max);
+        }
+        assert_eq!(full_jitter_backoff(Duration::ZERO, max, 3), Duration::ZERO);
+    }
+
+    #[test]
+    fn canonical_json_is_byte_stable() {
+        #[derive(Serialize)]
+        struct Inner {
+            zeta: u32,
+            alpha: u32,
+        }
+
+        #[derive(Serialize)]
+        struct Outer {
+            name: &'static str,
+            #[serde(flatten)]
+            inner: Inner,
+            nested: Vec<std::collections::HashMap<String, u32>>,
+        }
+
+        let value = || Outer {
+            name: "entry",
+            inner: Inner { zeta: 2, alpha: 1 },
+            nested: vec![(0..32).map(|i| (format!("key{i:02}"), i)).collect()],
+        };
+
+        let expected = to_canonical_json(&value()).unwrap();
+        assert!(
+            expected.starts_with(r#"{"alpha":1,"name":"entry","nested":[{"key00":0,"key01":1,"#)
+        );
+        assert!(expected.ends_with(r#""key31":31}],"zeta":2}"#));
+        // Every `HashMap` gets its own random iteration order
+        for _ in 0..10 {
+            assert_eq!(to_canonical_json(&value()).unwrap(), expected);
+        }
+    }
+}
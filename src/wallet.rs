@@ -1,30 +1,57 @@
+use std::path::{Path, PathBuf};
+
 use anyhow::Result;
 use clap::Parser;
 use ethers::{
+    core::k256,
     prelude::*,
-    types::transaction::{eip2718::TypedTransaction, eip712::Eip712},
+    signers::coins_bip39::English,
+    types::transaction::{
+        eip2718::TypedTransaction,
+        eip712::{EIP712Domain, Eip712},
+    },
+    utils::hash_message,
+};
+use log::info;
+use rusoto_core::{
+    credential::{ChainProvider, StaticProvider},
+    Region,
 };
-use rusoto_core::{credential::ContainerProvider, Region};
 use rusoto_kms::KmsClient;
+use zeroize::Zeroizing;
 
+use crate::{
+    gcp_kms::{GcpSigner, GcpSignerError},
+    reward::{Eip712RewardEntry, RewardEntry},
+};
+
+/// Signers are built once at startup and kept for the lifetime of the process. Each of them derives
+/// its address on construction, so `address()` never reaches the key store.
 #[derive(Debug)]
 pub enum Wallet {
     LocalWallet(LocalWallet),
     Aws(AwsSigner),
+    Gcp(GcpSigner),
 }
 
 #[derive(thiserror::Error, Debug)]
-#[error(transparent)]
 pub enum WalletError {
+    #[error(transparent)]
     LocalWallet(<LocalWallet as Signer>::Error),
-    Aws(<AwsSigner as Signer>::Error),
+    #[error(transparent)]
+    Aws(Box<<AwsSigner as Signer>::Error>),
+    #[error(transparent)]
+    Gcp(GcpSignerError),
+    #[error("signature recovery check failed: {0}")]
+    Recovery(String),
 }
 
-#[derive(Debug, Parser)]
+#[derive(Parser)]
 pub struct WalletConfig {
     #[clap(
         long,
         env = "PRIVATE_KEY",
+        value_parser = parse_private_key,
         help = "Private key of the account in plain text. (Only use for development)"
     )]
     private_key: Option<LocalWallet>,
@@ -34,12 +61,133 @@ pub struct WalletConfig {
         help = "Key ID for the AWS KMS key store. (Only use for production)"
     )]
     aws_key_id: Option<String>,
+    #[clap(
+        long,
+        env = "AWS_KEY_ID_SECONDARY",
+        requires = "aws_key_id",
+        help = "Key ID of a second AWS KMS key that signs alongside the first during a key rotation (optional)."
+    )]
+    aws_key_id_secondary: Option<String>,
     #[clap(
         long,
         env = "AWS_REGION",
         help = "AWS region for the AWS KMS key store. (Only use for production)"
     )]
     aws_region: Option<Region>,
+    #[clap(
+        long,
+        env = "AWS_ACCESS_KEY_ID",
+        requires = "aws_secret_access_key",
+        help = "Access key ID of static AWS credentials. Without it, credentials are looked up in the environment, the profile, and the container or instance metadata (optional)."
+    )]
+    aws_access_key_id: Option<String>,
+    #[clap(
+        long,
+        env = "AWS_SECRET_ACCESS_KEY",
+        requires = "aws_access_key_id",
+        help = "Secret access key of static AWS credentials (optional)."
+    )]
+    aws_secret_access_key: Option<String>,
+    #[clap(
+        long,
+        env = "AWS_SESSION_TOKEN",
+        requires = "aws_access_key_id",
+        help = "Session token of temporary static AWS credentials (optional)."
+    )]
+    aws_session_token: Option<String>,
+    #[clap(
+        long,
+        env = "GCP_KEY_RESOURCE_NAME",
+        help = "Resource name of the GCP Cloud KMS key version, authenticated through the instance's service account. (Only use for production)"
+    )]
+    gcp_key_resource_name: Option<String>,
+    #[clap(
+        long,
+        env = "KEYSTORE_PATH",
+        help = "Path to an encrypted JSON keystore file."
+    )]
+    keystore_path: Option<PathBuf>,
+    #[clap(
+        long,
+        env = "KEYSTORE_PASSWORD",
+        requires = "keystore_path",
+        conflicts_with = "keystore_password_file",
+        help = "Password of the keystore file."
+    )]
+    keystore_password: Option<String>,
+    #[clap(
+        long,
+        env = "KEYSTORE_PASSWORD_FILE",
+        requires = "keystore_path",
+        help = "Path to a file containing the password of the keystore file."
+    )]
+    keystore_password_file: Option<PathBuf>,
+    #[clap(
+        long,
+        env = "MNEMONIC",
+        help = "BIP-39 mnemonic phrase to derive the key from."
+    )]
+    mnemonic: Option<String>,
+    #[clap(
+        long,
+        env = "MNEMONIC_DERIVATION_PATH",
+        default_value = "m/44'/60'/0'/0/0",
+        requires = "mnemonic",
+        help = "BIP-32 derivation path of the key within the mnemonic."
+    )]
+    mnemonic_derivation_path: String,
+}
+
+impl std::fmt::Debug for WalletConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WalletConfig")
+            .field(
+                "private_key",
+                &self.private_key.as_ref().map(|_| "<redacted>"),
+            )
+            .field("aws_key_id", &self.aws_key_id)
+            .field("aws_key_id_secondary", &self.aws_key_id_secondary)
+            .field("aws_region", &self.aws_region)
+            .field("aws_access_key_id", &self.aws_access_key_id)
+            .field(
+                "aws_secret_access_key",
+                &self.aws_secret_access_key.as_ref().map(|_| "<redacted>"),
+            )
+            .field(
+                "aws_session_token",
+                &self.aws_session_token.as_ref().map(|_| "<redacted>"),
+            )
+            .field("gcp_key_resource_name", &self.gcp_key_resource_name)
+            .field("keystore_path", &self.keystore_path)
+            .field(
+                "keystore_password",
+                &self.keystore_password.as_ref().map(|_| "<redacted>"),
+            )
+            .field("keystore_password_file", &self.keystore_password_file)
+            .field("mnemonic", &self.mnemonic.as_ref().map(|_| "<redacted>"))
+            .field("mnemonic_derivation_path", &self.mnemonic_derivation_path)
+            .finish()
+    }
+}
+
+/// Accepts the key with or without `0x` and surrounding whitespace.
+fn parse_private_key(value: &str) -> Result<LocalWallet> {
+    let value = value.trim();
+    let value = value
+        .strip_prefix("0x")
+        .or_else(|| value.strip_prefix("0X"))
+        .unwrap_or(value);
+
+    let bytes =
+        hex::decode(value).map_err(|_| anyhow::anyhow!("private key must be 32 bytes of hex"))?;
+    if bytes.len() != 32 {
+        anyhow::bail!(
+            "private key must be 32 bytes of hex, got {} bytes",
+            bytes.len()
+        );
+    }
+
+    LocalWallet::from_bytes(&bytes).map_err(|_| anyhow::anyhow!("private key is not a valid key"))
 }
 
 pub trait WalletSource {
@@ -47,7 +195,27 @@ pub trait WalletSource {
 
     fn aws_key_id(&self) -> &Option<String>;
 
+    fn aws_key_id_secondary(&self) -> &Option<String>;
+
     fn aws_region(&self) -> &Option<Region>;
+
+    fn aws_access_key_id(&self) -> &Option<String>;
+
+    fn aws_secret_access_key(&self) -> &Option<String>;
+
+    fn aws_session_token(&self) -> &Option<String>;
+
+    fn gcp_key_resource_name(&self) -> &Option<String>;
+
+    fn keystore_path(&self) -> &Option<PathBuf>;
+
+    fn keystore_password(&self) -> &Option<String>;
+
+    fn keystore_password_file(&self) -> &Option<PathBuf>;
+
+    fn mnemonic(&self) -> &Option<String>;
+
+    fn mnemonic_derivation_path(&self) -> &str;
 }
 
 impl Wallet {
@@ -55,28 +223,192 @@ impl Wallet {
     where
         S: WalletSource,
     {
-        Ok(match (source.private_key(), source.aws_key_id()) {
-            (Some(private_key), None) => {
-                Wallet::LocalWallet(private_key.clone()).with_chain_id(chain_id)
+        let key_store_count = [
+            source.private_key().is_some(),
+            source.aws_key_id().is_some(),
+            source.gcp_key_resource_name().is_some(),
+            source.keystore_path().is_some(),
+            source.mnemonic().is_some(),
+        ]
+        .into_iter()
+        .filter(|provided| *provided)
+        .count();
+        match key_store_count {
+            0 => anyhow::bail!("no key store provided"),
+            1 => {}
+            _ => anyhow::bail!("more than 1 key store provided"),
+        }
+
+        Ok(if let Some(private_key) = source.private_key() {
+            Wallet::LocalWallet(private_key.clone()).with_chain_id(chain_id)
+        } else if let Some(aws_key_id) = source.aws_key_id() {
+            Self::from_aws(source, aws_key_id, chain_id).await?
+        } else if let Some(gcp_key_resource_name) = source.gcp_key_resource_name() {
+            Wallet::Gcp(GcpSigner::new(gcp_key_resource_name, chain_id).await?)
+        } else if let Some(keystore_path) = source.keystore_path() {
+            Self::from_keystore(source, keystore_path)?.with_chain_id(chain_id)
+        } else if let Some(mnemonic) = source.mnemonic() {
+            let wallet = MnemonicBuilder::<English>::default()
+                .phrase(mnemonic.as_str())
+                .derivation_path(source.mnemonic_derivation_path())
+                .map_err(|err| anyhow::anyhow!("invalid mnemonic derivation path: {}", err))?
+                .build()
+                .map_err(|err| anyhow::anyhow!("unable to derive key from mnemonic: {}", err))?;
+            Wallet::LocalWallet(wallet).with_chain_id(chain_id)
+        } else {
+            unreachable!()
+        })
+    }
+
+    fn from_keystore<S>(source: &S, keystore_path: &Path) -> Result<Self>
+    where
+        S: WalletSource,
+    {
+        let password = match (source.keystore_password(), source.keystore_password_file()) {
+            (Some(password), None) => Zeroizing::new(password.clone()),
+            (None, Some(password_file)) => {
+                let mut password =
+                    Zeroizing::new(std::fs::read_to_string(password_file).map_err(|err| {
+                        anyhow::anyhow!(
+                            "unable to read keystore password file {}: {}",
+                            password_file.display(),
+                            err
+                        )
+                    })?);
+                // Editors usually end files with a newline, which isn't part of the password
+                let len = password.trim_end_matches(['\r', '\n']).len();
+                password.truncate(len);
+                password
             }
-            (None, Some(aws_key_id)) => {
-                let aws_region = source
-                    .aws_region()
-                    .clone()
-                    .ok_or_else(|| anyhow::anyhow!("AWS region not provided"))?;
-
-                let kms_client = KmsClient::new_with_client(
-                    rusoto_core::Client::new_with(
-                        ContainerProvider::new(),
-                        rusoto_core::HttpClient::new().unwrap(),
-                    ),
-                    aws_region,
-                );
+            _ => anyhow::bail!("keystore password not provided"),
+        };
+
+        let wallet =
+            LocalWallet::decrypt_keystore(keystore_path, password.as_bytes()).map_err(|err| {
+                anyhow::anyhow!(
+                    "unable to decrypt keystore {}: {}",
+                    keystore_path.display(),
+                    err
+                )
+            })?;
+
+        Ok(Wallet::LocalWallet(wallet))
+    }
 
-                Wallet::Aws(AwsSigner::new(kms_client, aws_key_id, chain_id).await?)
+    /// Loads the secondary KMS key used during a key rotation, if configured.
+    pub async fn secondary_from_source<S>(source: &S, chain_id: u64) -> Result<Option<Self>>
+    where
+        S: WalletSource,
+    {
+        match source.aws_key_id_secondary() {
+            Some(aws_key_id) => Ok(Some(Self::from_aws(source, aws_key_id, chain_id).await?)),
+            None => Ok(None),
+        }
+    }
+
+    async fn from_aws<S>(source: &S, aws_key_id: &str, chain_id: u64) -> Result<Self>
+    where
+        S: WalletSource,
+    {
+        let aws_region = source
+            .aws_region()
+            .clone()
+            .ok_or_else(|| anyhow::anyhow!("AWS region not provided"))?;
+
+        let client = match (source.aws_access_key_id(), source.aws_secret_access_key()) {
+            (Some(access_key_id), Some(secret_access_key)) => rusoto_core::Client::new_with(
+                StaticProvider::new(
+                    access_key_id.clone(),
+                    secret_access_key.clone(),
+                    source.aws_session_token().clone(),
+                    None,
+                ),
+                rusoto_core::HttpClient::new().unwrap(),
+            ),
+            _ => rusoto_core::Client::new_with(
+                ChainProvider::new(),
+                rusoto_core::HttpClient::new().unwrap(),
+            ),
+        };
+        let kms_client = KmsClient::new_with_client(client, aws_region);
+
+        let signer = AwsSigner::new(kms_client, aws_key_id, chain_id).await?;
+        info!(
+            "AWS KMS key {} derives address {}",
+            aws_key_id,
+            ethers::utils::to_checksum(&signer.address(), None)
+        );
+
+        Ok(Wallet::Aws(signer))
+    }
+
+    /// Recovers the address that signed `entry` with the EIP-712 encoding used for signing, so that
+    /// signatures of other signers can be checked without their keys. `domain` must be the one of
+    /// the entry's period, including any override.
+    pub fn recover_reward_signer(
+        entry: &RewardEntry,
+        domain: &EIP712Domain,
+        signature: &Signature,
+    ) -> Result<Address> {
+        let digest = Eip712RewardEntry {
+            inner: entry,
+            domain,
+        }
+        .encode_eip712()?;
+
+        Ok(signature.recover(digest)?)
+    }
+
+    /// Resolves the signer address from the key store again instead of using the one cached at
+    /// startup, so that a rotated KMS key or switched role is noticed.
+    pub async fn resolve_address(&self) -> Result<Address> {
+        match self {
+            Self::LocalWallet(inner) => Ok(inner.address()),
+            // The key version is immutable, so its address can't change
+            Self::Gcp(inner) => Ok(inner.address()),
+            Self::Aws(inner) => {
+                let public_key = inner.get_pubkey().await?;
+                let public_key = k256::elliptic_curve::sec1::ToEncodedPoint::to_encoded_point(
+                    &public_key,
+                    false,
+                );
+                let hash = ethers::utils::keccak256(&public_key.as_bytes()[1..]);
+                Ok(Address::from_slice(&hash[12..]))
             }
-            _ => anyhow::bail!("more than 1 key store provided"),
-        })
+        }
+    }
+
+    /// KMS only returns `r` and `s`, so `v` is found by trial recovery. This makes sure exactly one
+    /// `v` recovers our address and that it's the one we got, instead of trusting the library.
+    fn ensure_recovers_to_self(
+        &self,
+        signature: Signature,
+        hash: H256,
+    ) -> Result<Signature, WalletError> {
+        let address = self.address();
+        let recovers_to_self = |signature: &Signature| {
+            signature
+                .recover(hash)
+                .map(|recovered| recovered == address)
+                .unwrap_or(false)
+        };
+
+        let matching_count = [27u64, 28]
+            .into_iter()
+            .filter(|v| recovers_to_self(&Signature { v: *v, ..signature }))
+            .count();
+        if matching_count != 1 {
+            return Err(WalletError::Recovery(format!(
+                "{matching_count} recovery ids match the signer address"
+            )));
+        }
+        if !recovers_to_self(&signature) {
+            return Err(WalletError::Recovery(String::from(
+                "signature does not recover to the signer address",
+            )));
+        }
+
+        Ok(signature)
     }
 }
 
@@ -93,7 +425,22 @@ impl Signer for Wallet {
                 .sign_message(message)
                 .await
                 .map_err(Self::Error::LocalWallet),
-            Self::Aws(inner) => inner.sign_message(message).await.map_err(Self::Error::Aws),
+            Self::Aws(inner) => {
+                let hash = hash_message(message.as_ref());
+                let signature = inner
+                    .sign_message(message)
+                    .await
+                    .map_err(|err| Self::Error::Aws(Box::new(err)))?;
+                self.ensure_recovers_to_self(signature, hash)
+            }
+            Self::Gcp(inner) => {
+                let hash = hash_message(message.as_ref());
+                let signature = inner
+                    .sign_message(message)
+                    .await
+                    .map_err(Self::Error::Gcp)?;
+                self.ensure_recovers_to_self(signature, hash)
+            }
         }
     }
 
@@ -103,10 +450,31 @@ impl Signer for Wallet {
                 .sign_transaction(message)
                 .await
                 .map_err(Self::Error::LocalWallet),
-            Self::Aws(inner) => inner
-                .sign_transaction(message)
-                .await
-                .map_err(Self::Error::Aws),
+            Self::Aws(inner) => {
+                // Mirrors the signer, which fills in its own chain id before hashing
+                let mut tx_with_chain = message.clone();
+                if tx_with_chain.chain_id().is_none() {
+                    tx_with_chain.set_chain_id(inner.chain_id());
+                }
+
+                let signature = inner
+                    .sign_transaction(message)
+                    .await
+                    .map_err(|err| Self::Error::Aws(Box::new(err)))?;
+                self.ensure_recovers_to_self(signature, tx_with_chain.sighash())
+            }
+            Self::Gcp(inner) => {
+                let mut tx_with_chain = message.clone();
+                if tx_with_chain.chain_id().is_none() {
+                    tx_with_chain.set_chain_id(inner.chain_id());
+                }
+
+                let signature = inner
+                    .sign_transaction(message)
+                    .await
+                    .map_err(Self::Error::Gcp)?;
+                self.ensure_recovers_to_self(signature, tx_with_chain.sighash())
+            }
         }
     }
 
@@ -119,10 +487,26 @@ impl Signer for Wallet {
                 .sign_typed_data(payload)
                 .await
                 .map_err(Self::Error::LocalWallet),
-            Self::Aws(inner) => inner
-                .sign_typed_data(payload)
-                .await
-                .map_err(Self::Error::Aws),
+            Self::Aws(inner) => {
+                let hash = payload
+                    .encode_eip712()
+                    .map_err(|err| WalletError::Recovery(err.to_string()))?;
+                let signature = inner
+                    .sign_typed_data(payload)
+                    .await
+                    .map_err(|err| Self::Error::Aws(Box::new(err)))?;
+                self.ensure_recovers_to_self(signature, hash.into())
+            }
+            Self::Gcp(inner) => {
+                let hash = payload
+                    .encode_eip712()
+                    .map_err(|err| WalletError::Recovery(err.to_string()))?;
+                let signature = inner
+                    .sign_typed_data(payload)
+                    .await
+                    .map_err(Self::Error::Gcp)?;
+                self.ensure_recovers_to_self(signature, hash.into())
+            }
         }
     }
 
@@ -130,6 +514,7 @@ impl Signer for Wallet {
         match self {
             Self::LocalWallet(inner) => inner.address(),
             Self::Aws(inner) => inner.address(),
+            Self::Gcp(inner) => inner.address(),
         }
     }
 
@@ -137,6 +522,7 @@ impl Signer for Wallet {
         match self {
             Self::LocalWallet(inner) => inner.chain_id(),
             Self::Aws(inner) => inner.chain_id(),
+            Self::Gcp(inner) => inner.chain_id(),
         }
     }
 
@@ -144,6 +530,7 @@ impl Signer for Wallet {
         match self {
             Self::LocalWallet(inner) => Self::LocalWallet(inner.with_chain_id(chain_id)),
             Self::Aws(inner) => Self::Aws(inner.with_chain_id(chain_id)),
+            Self::Gcp(inner) => Self::Gcp(inner.with_chain_id(chain_id)),
         }
     }
 }
@@ -157,7 +544,188 @@ impl WalletSource for WalletConfig {
         &self.aws_key_id
     }
 
+    fn aws_key_id_secondary(&self) -> &Option<String> {
+        &self.aws_key_id_secondary
+    }
+
     fn aws_region(&self) -> &Option<Region> {
         &self.aws_region
     }
+
+    fn aws_access_key_id(&self) -> &Option<String> {
+        &self.aws_access_key_id
+    }
+
+    fn aws_secret_access_key(&self) -> &Option<String> {
+        &self.aws_secret_access_key
+    }
+
+    fn aws_session_token(&self) -> &Option<String> {
+        &self.aws_session_token
+    }
+
+    fn gcp_key_resource_name(&self) -> &Option<String> {
+        &self.gcp_key_resource_name
+    }
+
+    fn keystore_path(&self) -> &Option<PathBuf> {
+        &self.keystore_path
+    }
+
+    fn keystore_password(&self) -> &Option<String> {
+        &self.keystore_password
+    }
+
+    fn keystore_password_file(&self) -> &Option<PathBuf> {
+        &self.keystore_password_file
+    }
+
+    fn mnemonic(&self) -> &Option<String> {
+        &self.mnemonic
+    }
+
+    fn mnemonic_derivation_path(&self) -> &str {
+        &self.mnemonic_derivation_path
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wallet() -> Wallet {
+        Wallet::LocalWallet(
+            "4c0883a69102937d6231471b5dbb6204fe5129617082792ae468d01a3f362318"
+                .parse()
+                .unwrap(),
+        )
+    }
+
+    const KEY: &str = "4c0883a69102937d6231471b5dbb6204fe5129617082792ae468d01a3f362318";
+
+    #[test]
+    fn private_key_is_accepted_with_or_without_prefix_and_whitespace() {
+        let expected = wallet().address();
+
+        for value in [
+            KEY.to_owned(),
+            format!("0x{KEY}"),
+            format!("0X{KEY}"),
+            format!("  0x{KEY}\n"),
+            KEY.to_uppercase(),
+        ] {
+            assert_eq!(parse_private_key(&value).unwrap().address(), expected);
+        }
+    }
+
+    #[test]
+    fn debug_output_redacts_secrets() {
+        let config = WalletConfig::try_parse_from([
+            "signer",
+            "--private-key",
+            KEY,
+            "--aws-access-key-id",
+            "AKIDEXAMPLE",
+            "--aws-secret-access-key",
+            "wJalrXUtnFEMI",
+            "--aws-session-token",
+            "FwoGZXIvYXdzEBY",
+        ])
+        .unwrap();
+
+        let debug = format!("{config:?}");
+
+        assert!(config.private_key.is_some());
+        for secret in [&KEY[..16], &KEY[48..], "wJalrXUtnFEMI", "FwoGZXIvYXdzEBY"] {
+            assert!(!debug.contains(secret), "{debug}");
+        }
+        assert!(debug.contains("AKIDEXAMPLE"));
+        assert!(debug.contains("<redacted>"));
+    }
+
+    #[test]
+    fn malformed_private_key_is_rejected() {
+        for (value, message) in [
+            ("", "private key must be 32 bytes of hex, got 0 bytes"),
+            ("0x", "private key must be 32 bytes of hex, got 0 bytes"),
+            (&KEY[1..], "private key must be 32 bytes of hex"),
+            (
+                &KEY[..62],
+                "private key must be 32 bytes of hex, got 31 bytes",
+            ),
+            ("0x0x4c08", "private key must be 32 bytes of hex"),
+            (
+                "zz0883a69102937d6231471b5dbb6204fe5129617082792ae468d01a3f362318",
+                "private key must be 32 bytes of hex",
+            ),
+            (
+                "0000000000000000000000000000000000000000000000000000000000000000",
+                "private key is not a valid key",
+            ),
+        ] {
+            assert_eq!(
+                parse_private_key(value).unwrap_err().to_string(),
+                message,
+                "{value}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn signature_of_own_key_passes_recovery_check() {
+        let wallet = wallet();
+        let signature = wallet.sign_message("reward").await.unwrap();
+
+        let checked = wallet
+            .ensure_recovers_to_self(signature, hash_message("reward"))
+            .unwrap();
+
+        assert_eq!(checked, signature);
+    }
+
+    #[tokio::test]
+    async fn signature_of_other_digest_fails_recovery_check() {
+        let wallet = wallet();
+        let signature = wallet.sign_message("reward").await.unwrap();
+
+        let err = wallet
+            .ensure_recovers_to_self(signature, hash_message("other"))
+            .unwrap_err();
+
+        assert!(matches!(err, WalletError::Recovery(_)), "{err}");
+    }
+
+    #[tokio::test]
+    async fn reward_signer_is_recovered_only_with_the_signing_domain() {
+        let wallet = wallet();
+        let entry = RewardEntry {
+            chain_id: 1,
+            period_id: 3,
+            recipient: Address::from_low_u64_be(1),
+            staking_reward: U256::from(100),
+            fee_reward: U256::from(20),
+            nonce: None,
+        };
+        let domain = crate::reward::reward_domain(Some(1), "RewardSystem", Address::zero());
+        let override_domain = EIP712Domain {
+            verifying_contract: Some(Address::from_low_u64_be(9)),
+            ..domain.clone()
+        };
+        let signature = wallet
+            .sign_typed_data(&Eip712RewardEntry {
+                inner: &entry,
+                domain: &override_domain,
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(
+            Wallet::recover_reward_signer(&entry, &override_domain, &signature).unwrap(),
+            wallet.address()
+        );
+        assert_ne!(
+            Wallet::recover_reward_signer(&entry, &domain, &signature).unwrap(),
+            wallet.address()
+        );
+    }
 }
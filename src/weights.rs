@@ -0,0 +1,389 @@
+use std::{
+    collections::{HashMap, HashSet},
+    time::SystemTime,
+};
+
+use anyhow::Result;
+use clap::ValueEnum;
+use ethers::prelude::*;
+use log::{info, warn};
+
+use crate::graphql::DebtEntry;
+
+/// Debt shares are 18-decimal fixed point, so all shares together are this much at any time.
+const DEBT_SHARE_UNIT: u64 = 1_000_000_000_000_000_000;
+
+/// Turns debt history into per-staker weights for a period window `[start, end)`.
+pub trait RewardWeightStrategy {
+    fn weights(
+        &self,
+        debt: &[DebtEntry],
+        window: (SystemTime, SystemTime),
+    ) -> HashMap<Address, U256>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum WeightStrategy {
+    TimeWeighted,
+    Flat,
+}
+
+/// Weights each staker by its debt share integrated over the time it was held within the window.
+#[derive(Debug, Default)]
+pub struct TimeWeightedStrategy;
+
+/// Debt state of a staker since its latest entry.
+struct DebtState {
+    debt_proportion: U256,
+    debt_factor: U256,
+    factor_integral_at_start: U256,
+}
+
+/// Gives every staker holding debt at some point within the window the same weight.
+#[derive(Debug, Default)]
+pub struct FlatStrategy;
+
+impl WeightStrategy {
+    pub fn build(self) -> Box<dyn RewardWeightStrategy + Send + Sync> {
+        match self {
+            Self::TimeWeighted => Box::new(TimeWeightedStrategy),
+            Self::Flat => Box::new(FlatStrategy),
+        }
+    }
+}
+
+impl RewardWeightStrategy for TimeWeightedStrategy {
+    fn weights(
+        &self,
+        debt: &[DebtEntry],
+        window: (SystemTime, SystemTime),
+    ) -> HashMap<Address, U256> {
+        let (start, end) = window;
+
+        // A staker's share at any time is `debt_proportion * global_factor / debt_factor`, where
+        // the global factor is the one recorded by the latest entry of any staker. Integrating the
+        // global factor over time lets each staker's weight be settled only when its own state
+        // changes.
+        let mut global_factor = U256::zero();
+        let mut factor_integral = U256::zero();
+        let mut last_time = start;
+        let mut states: HashMap<Address, DebtState> = HashMap::new();
+        let mut weights: HashMap<Address, U256> = HashMap::new();
+
+        let settle = |weights: &mut HashMap<Address, U256>,
+                      address: Address,
+                      state: DebtState,
+                      factor_integral: U256| {
+            if state.debt_factor.is_zero() {
+                return;
+            }
+            let weight = state
+                .debt_proportion
+                .checked_mul(factor_integral - state.factor_integral_at_start)
+                .expect("overflow")
+                / state.debt_factor;
+            let total = weights.entry(address).or_default();
+            *total = total.checked_add(weight).expect("overflow");
+        };
+
+        let mut entries = debt.iter().collect::<Vec<_>>();
+        entries.sort_by_key(|entry| entry.index);
+
+        for entry in entries.into_iter() {
+            if entry.timestamp >= end {
+                break;
+            }
+
+            if entry.timestamp > last_time {
+                let elapsed = entry.timestamp.duration_since(last_time).unwrap().as_secs();
+                factor_integral = factor_integral
+                    .checked_add(global_factor.checked_mul(elapsed.into()).expect("overflow"))
+                    .expect("overflow");
+                last_time = entry.timestamp;
+            }
+
+            if let Some(state) = states.remove(&entry.address) {
+                settle(&mut weights, entry.address, state, factor_integral);
+            }
+            states.insert(
+                entry.address,
+                DebtState {
+                    debt_proportion: entry.debt_proportion,
+                    debt_factor: entry.debt_factor,
+                    factor_integral_at_start: factor_integral,
+                },
+            );
+            global_factor = entry.debt_factor;
+        }
+
+        if end > last_time {
+            let elapsed = end.duration_since(last_time).unwrap().as_secs();
+            factor_integral = factor_integral
+                .checked_add(global_factor.checked_mul(elapsed.into()).expect("overflow"))
+                .expect("overflow");
+        }
+        for (address, state) in states.into_iter() {
+            settle(&mut weights, address, state, factor_integral);
+        }
+
+        weights.retain(|_, weight| !weight.is_zero());
+        weights
+    }
+}
+
+impl RewardWeightStrategy for FlatStrategy {
+    fn weights(
+        &self,
+        debt: &[DebtEntry],
+        window: (SystemTime, SystemTime),
+    ) -> HashMap<Address, U256> {
+        TimeWeightedStrategy
+            .weights(debt, window)
+            .into_keys()
+            .map(|address| (address, U256::one()))
+            .collect()
+    }
+}
+
+/// Orders weights by address. Dust of a split goes to the last recipient, so the order must not
+/// depend on `HashMap` iteration or signers computing the same period could disagree.
+pub fn into_sorted_weights(weights: HashMap<Address, U256>) -> Vec<(Address, U256)> {
+    let mut weights = weights.into_iter().collect::<Vec<_>>();
+    weights.sort_by_key(|(address, _)| *address);
+    weights
+}
+
+/// Guards against signing a period that pays nobody, e.g. because the subgraph returned zero debt
+/// proportions for active stakers. Stakers with debt entries but no weight are reported either way.
+pub fn validate_weights(
+    debt: &[DebtEntry],
+    weights: &HashMap<Address, U256>,
+    scheduled_reward: U256,
+) -> Result<()> {
+    let zero_weight_count = debt
+        .iter()
+        .map(|entry| entry.address)
+        .collect::<HashSet<_>>()
+        .into_iter()
+        .filter(|address| weights.get(address).is_none_or(|weight| weight.is_zero()))
+        .count();
+    if zero_weight_count > 0 {
+        warn!(
+            "{} addresses with debt entries have zero weight",
+            zero_weight_count
+        );
+    }
+
+    let has_weight = weights.values().any(|weight| !weight.is_zero());
+    if !has_weight && !scheduled_reward.is_zero() {
+        anyhow::bail!(
+            "total staker weight is zero for a scheduled reward of {} ({} addresses with zero weight)",
+            scheduled_reward,
+            zero_weight_count
+        );
+    }
+
+    Ok(())
+}
+
+/// Checks that time-weighted weights add up to the full debt share integrated over the part of
+/// `window` in which any debt existed. A shortfall or excess points at missing or extra debt entries
+/// from the subgraph, which would skew the split.
+pub fn check_debt_conservation(
+    debt: &[DebtEntry],
+    weights: &HashMap<Address, U256>,
+    window: (SystemTime, SystemTime),
+    tolerance_bps: u64,
+) -> Result<()> {
+    let (start, end) = window;
+
+    let first_debt_time = debt.iter().map(|entry| entry.timestamp).min();
+    let debt_start = match first_debt_time {
+        Some(first_debt_time) if first_debt_time < end => first_debt_time.max(start),
+        _ => return Ok(()),
+    };
+    let duration = end.duration_since(debt_start).unwrap().as_secs();
+
+    let expected = U256::from(DEBT_SHARE_UNIT)
+        .checked_mul(duration.into())
+        .expect("overflow");
+    let computed = weights.values().fold(U256::zero(), |acc, weight| {
+        acc.checked_add(*weight).expect("overflow")
+    });
+    info!(
+        "Debt integral: computed {}, expected {}",
+        computed, expected
+    );
+
+    let difference = if computed > expected {
+        computed - expected
+    } else {
+        expected - computed
+    };
+    let tolerance = expected * U256::from(tolerance_bps) / U256::from(10_000u64);
+    if difference > tolerance {
+        anyhow::bail!(
+            "debt integral {} deviates from expected {} by more than {} bps",
+            computed,
+            expected,
+            tolerance_bps
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+
+    use super::*;
+    use crate::distribution::split_pool;
+
+    const UNIT: u64 = DEBT_SHARE_UNIT;
+
+    fn at(seconds: u64) -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(seconds)
+    }
+
+    fn debt_entry(
+        index: u64,
+        address: Address,
+        debt_factor: u64,
+        debt_proportion: u64,
+        seconds: u64,
+    ) -> DebtEntry {
+        DebtEntry {
+            id: index.to_string(),
+            index,
+            address,
+            debt_factor: debt_factor.into(),
+            debt_proportion: debt_proportion.into(),
+            timestamp: at(seconds),
+        }
+    }
+
+    /// Alice holds all debt from 0, Bob mints the same amount at 50 which halves the global factor.
+    fn two_stakers() -> (Address, Address, Vec<DebtEntry>) {
+        let alice = Address::from_low_u64_be(1);
+        let bob = Address::from_low_u64_be(2);
+        let debt = vec![
+            debt_entry(0, alice, UNIT, UNIT, 0),
+            debt_entry(1, bob, UNIT / 2, UNIT / 2, 50),
+        ];
+        (alice, bob, debt)
+    }
+
+    #[test]
+    fn time_weighted_integrates_share_over_time() {
+        let (alice, bob, debt) = two_stakers();
+
+        let weights = TimeWeightedStrategy.weights(&debt, (at(0), at(100)));
+
+        assert_eq!(weights.len(), 2);
+        assert_eq!(weights[&alice], U256::from(UNIT) * 75);
+        assert_eq!(weights[&bob], U256::from(UNIT) * 25);
+        check_debt_conservation(&debt, &weights, (at(0), at(100)), 0).unwrap();
+    }
+
+    #[test]
+    fn time_weighted_split_matches_hand_computed_rewards() {
+        let (alice, bob, debt) = two_stakers();
+        let weights = into_sorted_weights(TimeWeightedStrategy.weights(&debt, (at(0), at(100))));
+
+        // Alice holds all debt for the first half and half of it for the second: 3/4 of the pool
+        let split = split_pool(U256::from(1_000_003u64), &weights, true).unwrap();
+
+        assert_eq!(
+            split.shares,
+            vec![
+                (alice, U256::from(750_002u64)),
+                (bob, U256::from(250_001u64)),
+            ]
+        );
+        assert_eq!(split.dust, U256::one());
+    }
+
+    #[test]
+    fn time_weighted_ignores_entries_after_the_window() {
+        let (alice, _, debt) = two_stakers();
+
+        let weights = TimeWeightedStrategy.weights(&debt, (at(0), at(50)));
+
+        assert_eq!(weights.len(), 1);
+        assert_eq!(weights[&alice], U256::from(UNIT) * 50);
+    }
+
+    #[test]
+    fn flat_gives_every_staker_the_same_weight() {
+        let (alice, bob, debt) = two_stakers();
+
+        let weights = FlatStrategy.weights(&debt, (at(0), at(100)));
+
+        assert_eq!(weights.len(), 2);
+        assert_eq!(weights[&alice], U256::one());
+        assert_eq!(weights[&bob], U256::one());
+    }
+
+    #[test]
+    fn zero_total_weight_is_rejected_only_with_a_scheduled_reward() {
+        let (_, _, debt) = two_stakers();
+        let weights = HashMap::new();
+
+        assert!(validate_weights(&debt, &weights, U256::from(1)).is_err());
+        assert!(validate_weights(&debt, &weights, U256::zero()).is_ok());
+    }
+
+    #[test]
+    fn zero_debt_proportions_fail_with_the_zero_weight_count() {
+        let debt = vec![
+            debt_entry(0, Address::from_low_u64_be(1), UNIT, 0, 0),
+            debt_entry(1, Address::from_low_u64_be(2), UNIT, 0, 10),
+        ];
+        let weights = TimeWeightedStrategy.weights(&debt, (at(0), at(100)));
+
+        let err = validate_weights(&debt, &weights, U256::from(1000)).unwrap_err();
+
+        assert_eq!(
+            err.to_string(),
+            "total staker weight is zero for a scheduled reward of 1000 (2 addresses with zero weight)"
+        );
+    }
+
+    #[test]
+    fn shuffled_entries_give_identical_sorted_weights_and_split() {
+        let entry = |index: u64| {
+            debt_entry(
+                index,
+                Address::from_low_u64_be(index % 7 + 1),
+                UNIT - index * 1_000,
+                UNIT / (index + 2),
+                index * 10,
+            )
+        };
+        let debt = (0..20).map(entry).collect::<Vec<_>>();
+        let window = (at(0), at(300));
+
+        let compute = |debt: &[DebtEntry]| {
+            let weights = into_sorted_weights(TimeWeightedStrategy.weights(debt, window));
+            let split = split_pool(U256::from(1_000_000_007u64), &weights, true).unwrap();
+            let bytes = serde_json::to_vec(&(&weights, &split.shares)).unwrap();
+            (weights, bytes)
+        };
+        let (expected_weights, expected_bytes) = compute(&debt);
+        assert_eq!(expected_weights.len(), 7);
+
+        let mut rng = StdRng::seed_from_u64(471);
+        for _ in 0..10 {
+            let mut order = (0..20).collect::<Vec<_>>();
+            order.shuffle(&mut rng);
+            let shuffled = order.into_iter().map(entry).collect::<Vec<_>>();
+
+            let (weights, bytes) = compute(&shuffled);
+            assert_eq!(weights, expected_weights);
+            assert_eq!(bytes, expected_bytes);
+        }
+    }
+}
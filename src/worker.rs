@@ -1,26 +1,63 @@
-use std::time::Duration;
+use std::{
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
 
 use anyhow::Result;
+use clap::Parser;
 use ethers::{prelude::*, utils::to_checksum};
-use log::debug;
-use reqwest::{Client as HttpClient, Url};
+use log::{debug, info};
+use reqwest::{
+    header::CONTENT_TYPE, Certificate, Client as HttpClient, ClientBuilder, Identity,
+    RequestBuilder, StatusCode, Url,
+};
 use serde::{Deserialize, Serialize};
 use serde_with::serde_as;
 use sha2::Digest;
 
 use crate::{
     config::RewardConfig,
-    custom_serde::{checksumed_address, hex_bytes, u256_dec, ChecksumedAddress},
+    custom_serde::{checksumed_address, hex_bytes, i256_dec, u256_dec, ChecksumedAddress},
+    http_log::BodyLogger,
+    rate_limit::RateLimiter,
+    util::{apply_signed_delta, truncate_for_log},
 };
 
+// Hard-coded params
+const PUBLISH_ERROR_SNIPPET_LENGTH: usize = 500;
+
 pub struct WorkerClient {
     client: HttpClient,
     base_url: Url,
     admin_token: String,
+    rate_limiter: Option<Arc<RateLimiter>>,
+    body_logger: Option<BodyLogger>,
+}
+
+#[derive(Debug, Parser)]
+pub struct WorkerTlsConfig {
+    #[clap(
+        long,
+        env = "WORKER_CLIENT_CERT",
+        help = "Path to a PEM file with the client certificate and private key for mutual TLS with the worker."
+    )]
+    worker_client_cert: Option<PathBuf>,
+    #[clap(
+        long,
+        env = "WORKER_CA_BUNDLE",
+        help = "Path to a PEM bundle of extra root certificates to trust for the worker."
+    )]
+    worker_ca_bundle: Option<PathBuf>,
+}
+
+pub struct WorkerTls {
+    identity: Option<Identity>,
+    ca_bundle: Option<Certificate>,
 }
 
 #[serde_as]
-#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Eq, Serialize, Deserialize)]
 pub struct WorkerConfig {
     pub first_period_start_time: u64,
     pub period_duration: u64,
@@ -36,6 +73,22 @@ pub struct Submission {
     pub signer: Address,
     pub entries: Vec<SubmissionRewardEntry>,
     pub composition: RewardComposition,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub attestation: Option<PeriodAttestation>,
+}
+
+/// Signature over the aggregate of a period, so that the worker can verify the submitter attested
+/// to the totals and not just to individual entries.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PeriodAttestation {
+    #[serde(with = "hex_bytes")]
+    pub entries_hash: Vec<u8>,
+    #[serde(with = "u256_dec")]
+    pub total_staking_reward: U256,
+    #[serde(with = "u256_dec")]
+    pub total_fee_reward: U256,
+    #[serde(with = "hex_bytes")]
+    pub signature: Vec<u8>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -50,6 +103,13 @@ pub struct SubmissionRewardEntry {
     pub signature: Vec<u8>,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StageOutcome {
+    Staged,
+    /// The worker already holds a submission from this signer for the period.
+    AlreadyStaged,
+}
+
 #[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct RewardComposition {
     #[serde(with = "u256_dec")]
@@ -60,171 +120,234 @@ pub struct RewardComposition {
     pub fees_accumulated: U256,
     #[serde(with = "u256_dec")]
     pub rollover_fees: U256,
+    /// Net of all manual staking reward adjustments applied to the period.
+    #[serde(default, with = "i256_dec")]
+    pub staking_adjustment: I256,
+    /// Net of all manual fee reward adjustments applied to the period.
+    #[serde(default, with = "i256_dec")]
+    pub fee_adjustment: I256,
+}
+
+impl WorkerTls {
+    /// Loads the PEM files referenced by the config and makes sure `reqwest` accepts them, so that
+    /// bad files are reported at startup instead of on the first worker request.
+    pub fn from_config(config: &WorkerTlsConfig) -> Result<Self> {
+        let identity = match &config.worker_client_cert {
+            Some(path) => {
+                let pem = std::fs::read(path).map_err(|err| {
+                    anyhow::anyhow!(
+                        "unable to read worker client cert {}: {}",
+                        path.display(),
+                        err
+                    )
+                })?;
+                Some(Identity::from_pem(&pem).map_err(|err| {
+                    anyhow::anyhow!("invalid worker client cert {}: {}", path.display(), err)
+                })?)
+            }
+            None => None,
+        };
+
+        let ca_bundle = match &config.worker_ca_bundle {
+            Some(path) => {
+                let pem = std::fs::read(path).map_err(|err| {
+                    anyhow::anyhow!(
+                        "unable to read worker CA bundle {}: {}",
+                        path.display(),
+                        err
+                    )
+                })?;
+                Some(Certificate::from_pem(&pem).map_err(|err| {
+                    anyhow::anyhow!("invalid worker CA bundle {}: {}", path.display(), err)
+                })?)
+            }
+            None => None,
+        };
+
+        let tls = Self {
+            identity,
+            ca_bundle,
+        };
+
+        // The rustls backend only parses certificates when the client is built
+        tls.apply(ClientBuilder::new())
+            .build()
+            .map_err(|err| anyhow::anyhow!("invalid worker TLS settings: {}", err))?;
+
+        Ok(tls)
+    }
+
+    fn apply(&self, mut builder: ClientBuilder) -> ClientBuilder {
+        if let Some(identity) = &self.identity {
+            builder = builder.identity(identity.clone());
+        }
+        if let Some(ca_bundle) = &self.ca_bundle {
+            builder = builder.add_root_certificate(ca_bundle.clone());
+        }
+        builder
+    }
+}
+
+impl std::fmt::Debug for WorkerClient {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WorkerClient")
+            .field("base_url", &self.base_url)
+            .field("admin_token", &"<redacted>")
+            .finish_non_exhaustive()
+    }
 }
 
 impl WorkerClient {
-    pub fn new(base_url: Url, admin_token: String, timeout: Duration) -> Self {
+    pub fn new(
+        base_url: Url,
+        admin_token: String,
+        timeout: Duration,
+        tls: &WorkerTls,
+        rate_limiter: Option<Arc<RateLimiter>>,
+        body_logger: Option<&BodyLogger>,
+    ) -> Self {
         Self {
-            client: reqwest::ClientBuilder::new()
+            client: tls
+                .apply(ClientBuilder::new())
                 .timeout(timeout)
                 .build()
                 .unwrap(),
+            body_logger: body_logger.map(|logger| logger.with_secret(&admin_token)),
             base_url,
             admin_token,
+            rate_limiter,
         }
     }
 
     pub async fn get_worker_config(&self) -> Result<Option<WorkerConfig>> {
-        let response = self
-            .client
-            .get(format!("{}admin/workerConfig", self.base_url))
-            .header("Authorization", format!("Bearer {}", self.admin_token))
-            .send()
+        let (status_code, response_text) = self
+            .send(
+                self.client
+                    .get(format!("{}admin/workerConfig", self.base_url)),
+            )
             .await?;
 
-        let status_code = response.status();
         if !status_code.is_success() {
-            let response_text = response.text().await?;
             debug!("Unsuccessful repsonse text: {}", response_text);
 
             anyhow::bail!("unsuccessful status code: {}", status_code);
         } else {
-            Ok(response.json().await?)
+            Ok(serde_json::from_str(&response_text)?)
         }
     }
 
     pub async fn get_reward_config_checked(&self, checksum: &[u8; 32]) -> Result<RewardConfig> {
-        let response = self
-            .client
-            .get(format!("{}admin/rewardConfig", self.base_url))
-            .header("Authorization", format!("Bearer {}", self.admin_token))
-            .send()
+        let raw_text = self.get_raw_reward_config().await?;
+
+        let hash_from_worker = reward_config_checksum(&raw_text);
+        if !checksum.eq(&hash_from_worker) {
+            anyhow::bail!(
+                "config checksum mismatch: expected: {}; actual: {}",
+                hex::encode(checksum),
+                hex::encode(hash_from_worker)
+            );
+        }
+
+        Ok(serde_json::from_str(&raw_text)?)
+    }
+
+    async fn get_raw_reward_config(&self) -> Result<String> {
+        let (status_code, response_text) = self
+            .send(
+                self.client
+                    .get(format!("{}admin/rewardConfig", self.base_url)),
+            )
             .await?;
 
-        let status_code = response.status();
         if !status_code.is_success() {
-            let response_text = response.text().await?;
             debug!("Unsuccessful repsonse text: {}", response_text);
 
             anyhow::bail!("unsuccessful status code: {}", status_code);
         } else {
-            let raw_text = response.text().await?;
-
-            let mut hasher = sha2::Sha256::default();
-            hasher.update(raw_text.as_bytes());
-
-            let hash_from_worker = hasher.finalize();
-            let hash_from_worker = hash_from_worker.as_slice();
-
-            if !checksum.eq(hash_from_worker) {
-                anyhow::bail!(
-                    "config checksum mismatch: expected: {}; actual: {}",
-                    hex::encode(checksum),
-                    hex::encode(hash_from_worker)
-                );
-            }
-
-            Ok(serde_json::from_str(&raw_text)?)
+            Ok(response_text)
         }
     }
 
     pub async fn get_last_period_id(&self) -> Result<u32> {
-        let response = self
-            .client
-            .get(format!("{}lastPeriodId", self.base_url))
-            .header("Authorization", format!("Bearer {}", self.admin_token))
-            .send()
+        let (status_code, response_text) = self
+            .send(self.client.get(format!("{}lastPeriodId", self.base_url)))
             .await?;
 
-        let status_code = response.status();
         if !status_code.is_success() {
-            let response_text = response.text().await?;
             debug!("Unsuccessful repsonse text: {}", response_text);
 
             anyhow::bail!("unsuccessful status code: {}", status_code);
         } else {
-            Ok(response.json().await?)
+            Ok(serde_json::from_str(&response_text)?)
         }
     }
 
     pub async fn get_signer_staged(&self, period_id: u32, signer: &Address) -> Result<bool> {
-        let response = self
-            .client
-            .get(format!(
+        let (status_code, response_text) = self
+            .send(self.client.get(format!(
                 "{}admin/signerStaged?periodId={}&signer={}",
                 self.base_url,
                 period_id,
                 to_checksum(signer, None)
-            ))
-            .header("Authorization", format!("Bearer {}", self.admin_token))
-            .send()
+            )))
             .await?;
 
-        let status_code = response.status();
         if !status_code.is_success() {
-            let response_text = response.text().await?;
             debug!("Unsuccessful repsonse text: {}", response_text);
 
             anyhow::bail!("unsuccessful status code: {}", status_code);
         } else {
-            Ok(response.json().await?)
+            Ok(serde_json::from_str(&response_text)?)
         }
     }
 
+    #[allow(dead_code)]
     pub async fn get_stage_ready(&self, period_id: u32) -> Result<bool> {
-        let response = self
-            .client
-            .get(format!(
+        let (status_code, response_text) = self
+            .send(self.client.get(format!(
                 "{}admin/stageReady?periodId={}",
                 self.base_url, period_id
-            ))
-            .header("Authorization", format!("Bearer {}", self.admin_token))
-            .send()
+            )))
             .await?;
 
-        let status_code = response.status();
         if !status_code.is_success() {
-            let response_text = response.text().await?;
             debug!("Unsuccessful repsonse text: {}", response_text);
 
             anyhow::bail!("unsuccessful status code: {}", status_code);
         } else {
-            Ok(response.json().await?)
+            Ok(serde_json::from_str(&response_text)?)
         }
     }
 
-    pub async fn set_worker_config(&self, config: &WorkerConfig) -> Result<()> {
-        let response = self
-            .client
-            .post(format!("{}admin/workerConfig", self.base_url))
-            .header("Authorization", format!("Bearer {}", self.admin_token))
-            .json(&config)
-            .send()
+    /// Number of signer submissions the worker requires before a period can be published.
+    pub async fn get_signature_threshold(&self, period_id: u32) -> Result<u32> {
+        let (status_code, response_text) = self
+            .send(self.client.get(format!(
+                "{}admin/signatureThreshold?periodId={}",
+                self.base_url, period_id
+            )))
             .await?;
 
-        let status_code = response.status();
         if !status_code.is_success() {
-            let response_text = response.text().await?;
             debug!("Unsuccessful repsonse text: {}", response_text);
 
             anyhow::bail!("unsuccessful status code: {}", status_code);
         } else {
-            Ok(())
+            Ok(serde_json::from_str(&response_text)?)
         }
     }
 
-    pub async fn stage(&self, submission: &Submission) -> Result<()> {
-        let response = self
-            .client
-            .post(format!("{}admin/stage", self.base_url))
-            .header("Authorization", format!("Bearer {}", self.admin_token))
-            .json(&submission)
-            .send()
+    #[allow(dead_code)]
+    pub async fn set_worker_config(&self, config: &WorkerConfig) -> Result<()> {
+        let (status_code, response_text) = self
+            .send(
+                self.client
+                    .post(format!("{}admin/workerConfig", self.base_url))
+                    .json(&config.canonicalized()),
+            )
             .await?;
 
-        let status_code = response.status();
         if !status_code.is_success() {
-            let response_text = response.text().await?;
             debug!("Unsuccessful repsonse text: {}", response_text);
 
             anyhow::bail!("unsuccessful status code: {}", status_code);
@@ -233,39 +356,528 @@ impl WorkerClient {
         }
     }
 
+    pub async fn stage(
+        &self,
+        submission: &Submission,
+        expected_signer: &Address,
+        expected_chain_id: u64,
+        allow_empty: bool,
+    ) -> Result<StageOutcome> {
+        submission
+            .validate(expected_signer, expected_chain_id, allow_empty)
+            .map_err(|err| anyhow::anyhow!("invalid submission: {err}"))?;
+
+        let (status_code, response_text) = self
+            .send(
+                self.client
+                    .post(format!("{}admin/stage", self.base_url))
+                    .json(&submission),
+            )
+            .await?;
+
+        if status_code == StatusCode::CONFLICT {
+            info!(
+                "Period {} already staged by signer {}",
+                submission.period_id,
+                to_checksum(&submission.signer, None)
+            );
+
+            Ok(StageOutcome::AlreadyStaged)
+        } else if !status_code.is_success() {
+            debug!("Unsuccessful repsonse text: {}", response_text);
+
+            anyhow::bail!("unsuccessful status code: {}", status_code);
+        } else {
+            Ok(StageOutcome::Staged)
+        }
+    }
+
+    #[allow(dead_code)]
     pub async fn publish(&self, period_id: u32) -> Result<()> {
-        let response = self
-            .client
-            .post(format!(
+        let (status_code, response_text) = self
+            .send(self.client.post(format!(
                 "{}admin/publish?periodId={}",
                 self.base_url, period_id
-            ))
-            .header("Authorization", format!("Bearer {}", self.admin_token))
-            .send()
+            )))
             .await?;
 
-        let status_code = response.status();
         if !status_code.is_success() {
-            let response_text = response.text().await?;
             debug!("Unsuccessful repsonse text: {}", response_text);
 
-            anyhow::bail!("unsuccessful status code: {}", status_code);
+            // The worker sends the publish transaction itself and reports a revert reason in the
+            // response body, which is the only place operators can see it
+            anyhow::bail!(
+                "unsuccessful status code: {}: {}",
+                status_code,
+                truncate_for_log(response_text.trim(), PUBLISH_ERROR_SNIPPET_LENGTH)
+            );
         } else {
             Ok(())
         }
     }
+
+    /// Sends an authorized request and reads the whole response body. Successful responses with a
+    /// body must be JSON, so that e.g. an HTML page from a misconfigured proxy isn't parsed as data.
+    async fn send(&self, request: RequestBuilder) -> Result<(StatusCode, String)> {
+        if let Some(rate_limiter) = &self.rate_limiter {
+            rate_limiter.acquire().await;
+        }
+
+        let request = request
+            .header("Authorization", format!("Bearer {}", self.admin_token))
+            .build()?;
+        let target = format!("Worker {} {}", request.method(), request.url().path());
+
+        if let Some(body_logger) = &self.body_logger {
+            let body = request
+                .body()
+                .and_then(|body| body.as_bytes())
+                .map(String::from_utf8_lossy)
+                .unwrap_or_default();
+            body_logger.log_request(&target, &body);
+        }
+
+        let response = self.client.execute(request).await?;
+        let status_code = response.status();
+        let content_type = response
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.to_owned());
+        let response_text = response.text().await?;
+
+        if let Some(body_logger) = &self.body_logger {
+            body_logger.log_response(&target, &response_text);
+        }
+
+        if status_code.is_success() && !response_text.trim().is_empty() {
+            let is_json = content_type.as_deref().is_some_and(|content_type| {
+                content_type
+                    .split(';')
+                    .next()
+                    .unwrap_or_default()
+                    .trim()
+                    .eq_ignore_ascii_case("application/json")
+            });
+            if !is_json {
+                anyhow::bail!(
+                    "{} responded with content type {} instead of application/json",
+                    target,
+                    content_type.as_deref().unwrap_or("<none>")
+                );
+            }
+        }
+
+        Ok((status_code, response_text))
+    }
+}
+
+impl WorkerConfig {
+    /// Returns a copy with the signer list sorted by address, so that the order in which signers
+    /// were configured never matters.
+    pub fn canonicalized(&self) -> Self {
+        let mut config = self.clone();
+        config.signers.sort();
+        config
+    }
+
+    /// Start of `period_id`. Boundaries are computed in unix seconds only, so neither time zones nor
+    /// leap seconds can shift them, and every step is checked for overflow.
+    pub fn period_start_time(&self, period_id: u32) -> Result<SystemTime> {
+        if self.period_duration == 0 {
+            anyhow::bail!("period duration must not be zero");
+        }
+
+        let start_secs = u64::from(period_id)
+            .checked_mul(self.period_duration)
+            .and_then(|offset| offset.checked_add(self.first_period_start_time))
+            .ok_or_else(|| anyhow::anyhow!("start time of period {} overflows", period_id))?;
+
+        SystemTime::UNIX_EPOCH
+            .checked_add(Duration::from_secs(start_secs))
+            .ok_or_else(|| anyhow::anyhow!("start time of period {} overflows", period_id))
+    }
+
+    /// Start and end of `period_id`. The start belongs to the period and the end to the next one.
+    pub fn period_window(&self, period_id: u32) -> Result<(SystemTime, SystemTime)> {
+        let next_period_id = period_id
+            .checked_add(1)
+            .ok_or_else(|| anyhow::anyhow!("period id {} overflows", period_id))?;
+
+        Ok((
+            self.period_start_time(period_id)?,
+            self.period_start_time(next_period_id)?,
+        ))
+    }
+
+    /// Period that `now` falls into. A time exactly on a boundary belongs to the period starting
+    /// there. Fails if `now` is before the first period.
+    pub fn current_period_id(&self, now: SystemTime) -> Result<u32> {
+        if self.period_duration == 0 {
+            anyhow::bail!("period duration must not be zero");
+        }
+
+        let now_secs = now
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_err(|_| anyhow::anyhow!("current time is before the unix epoch"))?
+            .as_secs();
+        let elapsed = now_secs
+            .checked_sub(self.first_period_start_time)
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "first period only starts at {}, {} seconds from now",
+                    self.first_period_start_time,
+                    self.first_period_start_time - now_secs
+                )
+            })?;
+
+        u32::try_from(elapsed / self.period_duration)
+            .map_err(|_| anyhow::anyhow!("current period id out of range"))
+    }
+
+    pub fn validate(&self, signer: &Address) -> Result<()> {
+        if self.period_duration == 0 {
+            anyhow::bail!("period duration must not be zero");
+        }
+        if self.signers.is_empty() {
+            anyhow::bail!("signer list is empty");
+        }
+        if !self.signers.contains(signer) {
+            anyhow::bail!(
+                "signer {} is not in the signer list",
+                to_checksum(signer, None)
+            );
+        }
+
+        Ok(())
+    }
+}
+
+impl Submission {
+    /// Catches malformed submissions before they're sent, as the worker only answers those with
+    /// a bare status code.
+    pub fn validate(
+        &self,
+        expected_signer: &Address,
+        expected_chain_id: u64,
+        allow_empty: bool,
+    ) -> Result<()> {
+        if self.entries.is_empty() && !allow_empty {
+            anyhow::bail!("period {} has no entries", self.period_id);
+        }
+        if &self.signer != expected_signer {
+            anyhow::bail!(
+                "signer {} does not match configured signer {}",
+                to_checksum(&self.signer, None),
+                to_checksum(expected_signer, None)
+            );
+        }
+        if self.chain_id != expected_chain_id {
+            anyhow::bail!(
+                "chain id {} does not match connected chain id {}",
+                self.chain_id,
+                expected_chain_id
+            );
+        }
+        if let Some(entry) = self.entries.iter().find(|entry| entry.signature.is_empty()) {
+            anyhow::bail!(
+                "entry for {} has an empty signature",
+                to_checksum(&entry.recipient, None)
+            );
+        }
+
+        Ok(())
+    }
+}
+
+impl PartialEq for WorkerConfig {
+    fn eq(&self, other: &Self) -> bool {
+        let lhs = self.canonicalized();
+        let rhs = other.canonicalized();
+
+        lhs.first_period_start_time == rhs.first_period_start_time
+            && lhs.period_duration == rhs.period_duration
+            && lhs.signers == rhs.signers
+    }
 }
 
 impl RewardComposition {
     pub fn staking_reward_for_period(&self) -> U256 {
-        self.scheduled_staking_rewards
+        let total = self
+            .scheduled_staking_rewards
             .checked_add(self.rollover_staking_rewards)
-            .expect("overflow")
+            .expect("overflow");
+        apply_signed_delta(total, self.staking_adjustment).expect("overflow")
     }
 
     pub fn fee_reward_for_period(&self) -> U256 {
-        self.fees_accumulated
+        let total = self
+            .fees_accumulated
             .checked_add(self.rollover_fees)
-            .expect("overflow")
+            .expect("overflow");
+        apply_signed_delta(total, self.fee_adjustment).expect("overflow")
+    }
+}
+
+/// SHA-256 over the raw config text as served by the worker.
+pub fn reward_config_checksum(raw_text: &str) -> [u8; 32] {
+    let mut hasher = sha2::Sha256::default();
+    hasher.update(raw_text.as_bytes());
+    hasher.finalize().into()
+}
+
+#[cfg(test)]
+mod tests {
+    use wiremock::{
+        matchers::{body_json, method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use super::*;
+
+    fn worker_client(server: &MockServer) -> WorkerClient {
+        WorkerClient::new(
+            format!("{}/", server.uri()).parse().unwrap(),
+            String::from("token"),
+            Duration::from_secs(5),
+            &WorkerTls {
+                identity: None,
+                ca_bundle: None,
+            },
+            None,
+            None,
+        )
+    }
+
+    #[test]
+    fn debug_output_redacts_the_admin_token() {
+        let worker_client = WorkerClient::new(
+            "http://localhost/".parse().unwrap(),
+            String::from("s3cr3t-admin-token"),
+            Duration::from_secs(5),
+            &WorkerTls {
+                identity: None,
+                ca_bundle: None,
+            },
+            None,
+            None,
+        );
+
+        let debug = format!("{worker_client:?}");
+
+        assert!(!debug.contains("s3cr3t-admin-token"), "{debug}");
+        assert!(debug.contains("<redacted>"));
+    }
+
+    #[tokio::test]
+    async fn html_success_response_is_rejected() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/lastPeriodId"))
+            .respond_with(ResponseTemplate::new(200).set_body_raw("<html>7</html>", "text/html"))
+            .mount(&server)
+            .await;
+
+        let err = worker_client(&server)
+            .get_last_period_id()
+            .await
+            .unwrap_err();
+
+        assert!(err.to_string().contains("content type text/html"), "{err}");
+    }
+
+    #[tokio::test]
+    async fn json_success_response_is_parsed() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/lastPeriodId"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_raw("7", "application/json; charset=utf-8"),
+            )
+            .mount(&server)
+            .await;
+
+        assert_eq!(
+            worker_client(&server).get_last_period_id().await.unwrap(),
+            7
+        );
+    }
+
+    fn submission(signer: Address) -> Submission {
+        Submission {
+            period_id: 7,
+            chain_id: 1,
+            signer,
+            entries: vec![SubmissionRewardEntry {
+                recipient: Address::from_low_u64_be(1),
+                staking_reward: U256::from(100),
+                fee_reward: U256::from(20),
+                signature: vec![1; 65],
+            }],
+            composition: RewardComposition::default(),
+            attestation: None,
+        }
+    }
+
+    async fn stage_with_status(status_code: u16) -> Result<StageOutcome> {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/admin/stage"))
+            .respond_with(ResponseTemplate::new(status_code))
+            .mount(&server)
+            .await;
+
+        let signer = Address::from_low_u64_be(9);
+        worker_client(&server)
+            .stage(&submission(signer), &signer, 1, false)
+            .await
+    }
+
+    #[tokio::test]
+    async fn conflict_on_stage_means_already_staged() {
+        assert_eq!(
+            stage_with_status(409).await.unwrap(),
+            StageOutcome::AlreadyStaged
+        );
+        assert_eq!(stage_with_status(200).await.unwrap(), StageOutcome::Staged);
+        assert!(stage_with_status(500).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn publish_error_carries_the_revert_reason() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/admin/publish"))
+            .respond_with(
+                ResponseTemplate::new(500)
+                    .set_body_string("execution reverted: period not ready\n"),
+            )
+            .mount(&server)
+            .await;
+
+        let err = worker_client(&server).publish(7).await.unwrap_err();
+
+        assert_eq!(
+            err.to_string(),
+            "unsuccessful status code: 500 Internal Server Error: execution reverted: period not ready"
+        );
+    }
+
+    fn worker_config(signers: Vec<Address>) -> WorkerConfig {
+        WorkerConfig {
+            first_period_start_time: 1_600_000_000,
+            period_duration: 604_800,
+            signers,
+        }
+    }
+
+    #[test]
+    fn config_without_our_signer_is_rejected() {
+        let signer = Address::from_low_u64_be(1);
+        let other_signer = Address::from_low_u64_be(2);
+
+        assert!(worker_config(vec![other_signer, signer])
+            .validate(&signer)
+            .is_ok());
+        assert_eq!(
+            worker_config(vec![])
+                .validate(&signer)
+                .unwrap_err()
+                .to_string(),
+            "signer list is empty"
+        );
+        assert!(worker_config(vec![other_signer])
+            .validate(&signer)
+            .unwrap_err()
+            .to_string()
+            .contains("is not in the signer list"));
+    }
+
+    #[test]
+    fn signer_order_does_not_matter() {
+        let signers = [3, 1, 2].map(Address::from_low_u64_be);
+        let config = worker_config(signers.to_vec());
+        let reordered = worker_config(vec![signers[1], signers[2], signers[0]]);
+
+        assert_eq!(config, reordered);
+        assert_ne!(config, worker_config(signers[..2].to_vec()));
+    }
+
+    #[tokio::test]
+    async fn worker_config_is_sent_with_sorted_signers() {
+        let signers = [3, 1, 2].map(Address::from_low_u64_be);
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/admin/workerConfig"))
+            .and(body_json(worker_config(vec![
+                signers[1], signers[2], signers[0],
+            ])))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(2)
+            .mount(&server)
+            .await;
+
+        let worker_client = worker_client(&server);
+        for signers in [signers.to_vec(), vec![signers[2], signers[0], signers[1]]] {
+            worker_client
+                .set_worker_config(&worker_config(signers))
+                .await
+                .unwrap();
+        }
+    }
+
+    #[test]
+    fn period_boundaries_stay_in_unix_seconds() {
+        let config = worker_config(vec![]);
+        let at = |seconds: u64| SystemTime::UNIX_EPOCH + Duration::from_secs(seconds);
+
+        assert_eq!(
+            config.period_window(2).unwrap(),
+            (at(1_601_209_600), at(1_601_814_400))
+        );
+        assert_eq!(config.current_period_id(at(1_601_209_600)).unwrap(), 2);
+        assert_eq!(config.current_period_id(at(1_601_209_599)).unwrap(), 1);
+        assert!(config.current_period_id(at(1_599_999_999)).is_err());
+    }
+
+    #[test]
+    fn period_boundaries_near_the_u64_limit_fail_instead_of_wrapping() {
+        // `SystemTime` ends at `i64::MAX` seconds on unix, well before the seconds overflow
+        let last_secs = i64::MAX as u64;
+        let config = WorkerConfig {
+            first_period_start_time: last_secs - 10,
+            period_duration: 5,
+            signers: vec![],
+        };
+        assert_eq!(
+            config.period_start_time(2).unwrap(),
+            SystemTime::UNIX_EPOCH + Duration::from_secs(last_secs)
+        );
+        assert!(config.period_start_time(3).is_err());
+        assert!(config.period_window(2).is_err());
+
+        let config = WorkerConfig {
+            first_period_start_time: u64::MAX - 10,
+            ..config
+        };
+        assert!(config.period_start_time(3).is_err());
+
+        let config = WorkerConfig {
+            period_duration: u64::MAX,
+            ..worker_config(vec![])
+        };
+        assert!(config.period_window(u32::MAX).is_err());
+    }
+
+    #[test]
+    fn zero_period_duration_is_rejected() {
+        let config = WorkerConfig {
+            period_duration: 0,
+            ..worker_config(vec![Address::zero()])
+        };
+
+        assert!(config.period_start_time(1).is_err());
+        assert!(config.current_period_id(SystemTime::now()).is_err());
+        assert!(config.validate(&Address::zero()).is_err());
     }
 }